@@ -1,60 +1,183 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Version of the serialized DAG format, bumped whenever reading an older file needs a migration
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Largest branch ID a DAG file may contain, leaving room to keep allocating IDs above it
+pub const MAX_BRANCH_ID: usize = u32::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct BranchId(pub usize);
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A rebase that failed, kept so the failure can be reported and reproduced later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FailedRebase {
+    /// What the branch was being rebased onto, e.g. its parent's name or origin/<branch>
+    pub target: String,
+    /// The commit the target pointed at during the rebase; empty if it couldn't be resolved,
+    /// or if the failure was recorded before dagit kept track of it
+    #[serde(default)]
+    pub target_commit: String,
+}
+
+impl FailedRebase {
+    pub fn new(target: impl Into<String>, target_commit: impl Into<String>) -> Self {
+        FailedRebase { target: target.into(), target_commit: target_commit.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Branch {
     /// Unique identifier for the branch
     pub uid: BranchId,
     /// UIDs of the parent branches
+    #[serde(default)]
     pub parents: Vec<BranchId>,
     /// UIDs of the child branches
+    #[serde(default)]
     pub children: Vec<BranchId>,
     /// Git branch name
     pub git_name: String,
-    /// Last failed rebase attempt (target branch name)
-    pub last_failed_rebase: Option<String>,
+    /// Last failed rebase attempt
+    #[serde(default)]
+    pub last_failed_rebase: Option<FailedRebase>,
     /// Pull request number associated with this branch
+    #[serde(default)]
     pub pr_number: Option<usize>,
+    /// Free-text note about the branch, shown in the dag view
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Name of the branch on origin, when it differs from the local name (e.g. `alice/feature`)
+    #[serde(default)]
+    pub remote_name: Option<String>,
 }
 
 impl Branch {
-    /// Create a new Branch with a specific ID (used internally by DAG)
-    pub(crate) fn with_id(uid: BranchId, git_name: String) -> Self {
+    /// Create a branch with the given ID and no relationships
+    /// The caller is responsible for the ID being unique; Dag::create_branch picks one automatically
+    pub fn new(uid: BranchId, git_name: impl Into<String>) -> Self {
         Branch {
             uid,
             parents: Vec::new(),
             children: Vec::new(),
-            git_name,
+            git_name: git_name.into(),
             last_failed_rebase: None,
             pr_number: None,
+            note: None,
+            remote_name: None,
         }
     }
+
+    /// The name the branch is pushed under and has on origin
+    pub fn remote_branch_name(&self) -> &str {
+        self.remote_name.as_deref().unwrap_or(&self.git_name)
+    }
+
+    /// Create a new Branch with a specific ID (used internally by DAG)
+    pub(crate) fn with_id(uid: BranchId, git_name: String) -> Self {
+        Branch::new(uid, git_name)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Sync status of a branch relative to its parents, as shown in the dag view
+/// Computing it needs git, so the binary resolves it; the DAG only aggregates it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BranchStatus {
+    /// Every parent is an ancestor of the branch
+    UpToDate,
+    /// At least one parent has commits the branch isn't based on
+    OutOfDate,
+    /// The last update failed to rebase the branch
+    FailedUpdate,
+    /// Ancestry couldn't be checked, e.g. because the branch was deleted in git
+    Unknown,
+    /// The branch is a root, so there is nothing to be up to date with
+    NoParent,
+}
+
+impl BranchStatus {
+    /// Single glyph for the status, for the compact dag view
+    pub fn glyph(self) -> &'static str {
+        match self {
+            BranchStatus::UpToDate => "✅",
+            BranchStatus::OutOfDate => "🔄",
+            BranchStatus::FailedUpdate => "❌",
+            BranchStatus::Unknown => "❓",
+            BranchStatus::NoParent => "⚪",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            BranchStatus::UpToDate => "up to date",
+            BranchStatus::OutOfDate => "out of date",
+            BranchStatus::FailedUpdate => "failed update",
+            BranchStatus::Unknown => "unknown",
+            BranchStatus::NoParent => "no parent",
+        }
+    }
+}
+
+impl std::fmt::Display for BranchStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.glyph(), self.description())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Dag {
+    /// Version of the format this DAG was serialized with (0 for files that predate versioning)
+    #[serde(default)]
+    pub schema_version: u32,
     /// Map from branch UID to Branch
     pub branches: HashMap<BranchId, Branch>,
     /// Next available branch ID (used for generating unique IDs)
+    #[serde(default)]
     next_branch_id: usize,
+    /// URL of the origin remote of the repository this DAG was last written in
+    #[serde(default)]
+    pub remote_url: Option<String>,
+    /// Order in which submit last processed the branches, see reconcile_submit_order
+    #[serde(default)]
+    pub submit_order: Vec<BranchId>,
+    /// Branch pinned as the trunk with `dagit set-trunk`, instead of the configured or default branch
+    #[serde(default)]
+    pub trunk: Option<BranchId>,
+}
+
+impl Default for Dag {
+    fn default() -> Self {
+        Dag::new()
+    }
 }
 
 impl Dag {
     /// Create a new empty Dag
     pub fn new() -> Self {
         Dag {
+            schema_version: SCHEMA_VERSION,
             branches: HashMap::new(),
             next_branch_id: 1,
+            remote_url: None,
+            submit_order: Vec::new(),
+            trunk: None,
         }
     }
     
     /// Create a new branch with an automatically generated unique ID
+    /// The stored counter is not trusted on its own (the DAG file may have been edited by
+    /// hand), so the new ID is also kept above every existing ID; loading rejects IDs above
+    /// `MAX_BRANCH_ID` (see `check_branch_ids`), so the increments below can't overflow
     pub fn create_branch(&mut self, git_name: String) -> BranchId {
-        let branch_id = BranchId(self.next_branch_id);
-        self.next_branch_id += 1;
+        let above_existing = self.branches.keys()
+            .map(|id| id.0.checked_add(1).expect("Branch ID overflow"))
+            .max()
+            .unwrap_or(1);
+        let branch_id = BranchId(self.next_branch_id.max(above_existing));
+        self.next_branch_id = branch_id.0.checked_add(1).expect("Branch ID overflow");
         
         let branch = Branch::with_id(branch_id, git_name);
         self.branches.insert(branch_id, branch);
@@ -63,12 +186,22 @@ impl Dag {
     }
     
     /// Insert a branch into the DAG (for when you already have a branch with an ID)
-    pub fn insert_branch(&mut self, branch: Branch) {
+    /// Returns an error if the ID is above `MAX_BRANCH_ID`, like loading a DAG file would
+    pub fn insert_branch(&mut self, branch: Branch) -> Result<(), String> {
+        if branch.uid.0 > MAX_BRANCH_ID {
+            return Err(format!("Branch ID {} is larger than the maximum of {}", branch.uid.0, MAX_BRANCH_ID));
+        }
         // Update next_branch_id to ensure we don't generate duplicate IDs
         self.next_branch_id = self.next_branch_id.max(branch.uid.0 + 1);
         self.branches.insert(branch.uid, branch);
+        Ok(())
     }
     
+    /// The pinned trunk branch, if there is one and it is still tracked
+    pub fn trunk_branch(&self) -> Option<&Branch> {
+        self.trunk.and_then(|trunk| self.branches.get(&trunk))
+    }
+
     /// Get a branch by its UID
     pub fn get_branch(&self, uid: &BranchId) -> Option<&Branch> {
         self.branches.get(uid)
@@ -79,8 +212,11 @@ impl Dag {
         self.branches.get_mut(uid)
     }
     
-    /// Remove a branch from the DAG
+    /// Remove a branch from the DAG, unpinning it if it was the trunk
     pub fn remove_branch(&mut self, uid: &BranchId) -> Option<Branch> {
+        if self.trunk == Some(*uid) {
+            self.trunk = None;
+        }
         self.branches.remove(uid)
     }
     
@@ -104,12 +240,24 @@ impl Dag {
         self.branches.values().find(|branch| branch.git_name == git_name)
     }
     
+    /// Find a branch by its git name, for modifying it
+    pub fn find_branch_by_name_mut(&mut self, git_name: &str) -> Option<&mut Branch> {
+        self.branches.values_mut().find(|branch| branch.git_name == git_name)
+    }
+    
     /// Get all git branch names that are currently tracked
     pub fn get_tracked_branch_names(&self) -> Vec<String> {
         self.branches.values().map(|branch| branch.git_name.clone()).collect()
     }
     
+    /// Iterate over every (parent, child) edge, in no particular order
+    pub fn edges(&self) -> impl Iterator<Item = (BranchId, BranchId)> + '_ {
+        self.branches.iter()
+            .flat_map(|(parent_id, branch)| branch.children.iter().map(move |child_id| (*parent_id, *child_id)))
+    }
+    
     /// Add a parent relationship (this also adds the corresponding child relationship)
+    /// Fails if the parent is the child itself or one of its descendants, since that would create a cycle
     pub fn add_parent_child_relationship(&mut self, child_name: &str, parent_name: &str) -> Result<(), String> {
         // Find the child and parent branches
         let child_id = self.find_branch_by_name(child_name)
@@ -119,25 +267,16 @@ impl Dag {
         let parent_id = self.find_branch_by_name(parent_name)
             .map(|branch| branch.uid)
             .ok_or_else(|| format!("Parent branch '{}' not found in DAG", parent_name))?;
-        
-        // Add parent to child's parents list (if not already present)
-        if let Some(child_branch) = self.branches.get_mut(&child_id) {
-            if !child_branch.parents.contains(&parent_id) {
-                child_branch.parents.push(parent_id);
-            }
-        }
-        
-        // Add child to parent's children list (if not already present)
-        if let Some(parent_branch) = self.branches.get_mut(&parent_id) {
-            if !parent_branch.children.contains(&child_id) {
-                parent_branch.children.push(child_id);
-            }
+
+        if child_id == parent_id || self.is_ancestor_in_dag(child_id, parent_id) {
+            return Err(format!("Cannot make '{}' a parent of '{}': it would create a cycle", parent_name, child_name));
         }
-        
-        Ok(())
+
+        self.add_parent_child_relationship_by_id(child_id, parent_id)
     }
 
     /// Add a parent relationship by branch IDs (this also adds the corresponding child relationship)
+    /// Fails if the parent is the child itself or one of its descendants, since that would create a cycle
     pub fn add_parent_child_relationship_by_id(&mut self, child_id: BranchId, parent_id: BranchId) -> Result<(), String> {
         // Verify both branches exist
         if !self.branches.contains_key(&child_id) {
@@ -146,6 +285,9 @@ impl Dag {
         if !self.branches.contains_key(&parent_id) {
             return Err(format!("Parent branch with ID {} not found in DAG", parent_id.0));
         }
+        if child_id == parent_id || self.is_ancestor_in_dag(child_id, parent_id) {
+            return Err(format!("Cannot make branch with ID {} a parent of {}: it would create a cycle", parent_id.0, child_id.0));
+        }
         
         // Add parent to child's parents list (if not already present)
         if let Some(child_branch) = self.branches.get_mut(&child_id) {
@@ -164,46 +306,436 @@ impl Dag {
         Ok(())
     }
     
+    /// Make the given parent the primary (first) parent of the child
+    /// The primary parent determines the rebase target and the PR base
+    pub fn set_primary_parent(&mut self, child: BranchId, parent: BranchId) -> Result<(), String> {
+        let child_branch = self.branches.get_mut(&child)
+            .ok_or_else(|| format!("Child branch with ID {} not found in DAG", child.0))?;
+
+        let position = child_branch.parents.iter().position(|&p| p == parent)
+            .ok_or_else(|| format!("Branch with ID {} is not a parent of branch '{}'", parent.0, child_branch.git_name))?;
+
+        // Move the parent to the front, keeping the relative order of the others
+        let parent_id = child_branch.parents.remove(position);
+        child_branch.parents.insert(0, parent_id);
+
+        Ok(())
+    }
+    
+    /// Replace all parents of the child with the single new parent
+    /// Fails if the new parent is the child itself or one of its descendants
+    pub fn reparent(&mut self, child: BranchId, new_parent: BranchId) -> Result<(), String> {
+        if !self.branches.contains_key(&new_parent) {
+            return Err(format!("Parent branch with ID {} not found in DAG", new_parent.0));
+        }
+        let old_parents = self.branches.get(&child)
+            .map(|branch| branch.parents.clone())
+            .ok_or_else(|| format!("Child branch with ID {} not found in DAG", child.0))?;
+
+        if child == new_parent || self.is_ancestor_in_dag(child, new_parent) {
+            return Err(format!("Cannot move branch with ID {} under its own descendant {}", child.0, new_parent.0));
+        }
+
+        // Detach the child from its old parents
+        for parent_id in old_parents {
+            if let Some(parent_branch) = self.branches.get_mut(&parent_id) {
+                parent_branch.children.retain(|&c| c != child);
+            }
+        }
+        if let Some(child_branch) = self.branches.get_mut(&child) {
+            child_branch.parents.clear();
+        }
+
+        self.add_parent_child_relationship_by_id(child, new_parent)
+    }
+    
+    /// Remove all of a branch's parent and child relationships, keeping the branch itself
+    /// Its former children lose it as a parent, and may become roots
+    pub fn detach(&mut self, uid: BranchId) -> Result<(), String> {
+        let branch = self.branches.get_mut(&uid)
+            .ok_or_else(|| format!("Branch with ID {} not found in DAG", uid.0))?;
+        let parents = std::mem::take(&mut branch.parents);
+        let children = std::mem::take(&mut branch.children);
+
+        for parent_id in parents {
+            if let Some(parent) = self.branches.get_mut(&parent_id) {
+                parent.children.retain(|&c| c != uid);
+            }
+        }
+        for child_id in children {
+            if let Some(child) = self.branches.get_mut(&child_id) {
+                child.parents.retain(|&p| p != uid);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Splice `new` into the edge from `parent` to `child`, so that `child` hangs under `new`
+    /// and `new` under `parent`. `new` takes `parent`'s place in the child's parent list, so
+    /// it becomes the primary parent if `parent` was
+    pub fn insert_between(&mut self, new: BranchId, parent: BranchId, child: BranchId) -> Result<(), String> {
+        for id in [new, parent, child] {
+            if !self.branches.contains_key(&id) {
+                return Err(format!("Branch with ID {} not found in DAG", id.0));
+            }
+        }
+        let position = self.branches[&child].parents.iter().position(|&p| p == parent)
+            .ok_or_else(|| format!("Branch with ID {} is not a parent of branch with ID {}", parent.0, child.0))?;
+        if new == parent || new == child || self.is_ancestor_in_dag(new, parent) || self.is_ancestor_in_dag(child, new) {
+            return Err(format!("Cannot insert branch with ID {} between {} and {}: it would create a cycle", new.0, parent.0, child.0));
+        }
+
+        if let Some(parent_branch) = self.branches.get_mut(&parent) {
+            parent_branch.children.retain(|&c| c != child);
+        }
+        if let Some(child_branch) = self.branches.get_mut(&child) {
+            if child_branch.parents.contains(&new) {
+                child_branch.parents.remove(position);
+            } else {
+                child_branch.parents[position] = new;
+            }
+        }
+        if let Some(new_branch) = self.branches.get_mut(&new) {
+            if !new_branch.children.contains(&child) {
+                new_branch.children.push(child);
+            }
+        }
+
+        self.add_parent_child_relationship_by_id(new, parent)
+    }
+
+    /// Fold `src` into `dst`: all of src's children become children of dst and src is removed
+    /// Where src was a child's parent, dst takes its place in the child's parent list
+    pub fn merge_into(&mut self, src: BranchId, dst: BranchId) -> Result<(), String> {
+        if src == dst {
+            return Err(format!("Cannot merge branch with ID {} into itself", src.0));
+        }
+        if !self.branches.contains_key(&dst) {
+            return Err(format!("Destination branch with ID {} not found in DAG", dst.0));
+        }
+        let (src_parents, src_children) = self.branches.get(&src)
+            .map(|branch| (branch.parents.clone(), branch.children.clone()))
+            .ok_or_else(|| format!("Source branch with ID {} not found in DAG", src.0))?;
+
+        // Hanging src's children under one of their own descendants would create a cycle
+        if self.is_ancestor_in_dag(src, dst) {
+            return Err(format!("Cannot merge branch with ID {} into its descendant {}", src.0, dst.0));
+        }
+
+        for parent_id in src_parents {
+            if let Some(parent_branch) = self.branches.get_mut(&parent_id) {
+                parent_branch.children.retain(|&c| c != src);
+            }
+        }
+
+        for child_id in src_children {
+            if let Some(child_branch) = self.branches.get_mut(&child_id) {
+                if child_branch.parents.contains(&dst) {
+                    child_branch.parents.retain(|&p| p != src);
+                } else {
+                    for parent_id in child_branch.parents.iter_mut().filter(|p| **p == src) {
+                        *parent_id = dst;
+                    }
+                }
+            }
+            if let Some(dst_branch) = self.branches.get_mut(&dst) {
+                if !dst_branch.children.contains(&child_id) {
+                    dst_branch.children.push(child_id);
+                }
+            }
+        }
+
+        self.branches.remove(&src);
+        if self.trunk == Some(src) {
+            self.trunk = Some(dst);
+        }
+        Ok(())
+    }
+
+    /// Remove every branch whose git name isn't in `existing`, e.g. branches deleted in git
+    /// Each removed branch's children are relinked to its parents, keeping their place so the
+    /// primary parent stays first. Returns the names of the removed branches, sorted
+    pub fn retain_existing(&mut self, existing: &HashSet<String>) -> Vec<String> {
+        let mut stale: Vec<BranchId> = self.branches.values()
+            .filter(|branch| !existing.contains(&branch.git_name))
+            .map(|branch| branch.uid)
+            .collect();
+        stale.sort_by_key(|id| id.0);
+
+        let mut removed = Vec::new();
+        for branch_id in stale {
+            if let Some(branch) = self.remove_branch(&branch_id) {
+                for parent_id in &branch.parents {
+                    if let Some(parent_branch) = self.branches.get_mut(parent_id) {
+                        splice(&mut parent_branch.children, branch_id, &branch.children);
+                    }
+                }
+                for child_id in &branch.children {
+                    if let Some(child_branch) = self.branches.get_mut(child_id) {
+                        splice(&mut child_branch.parents, branch_id, &branch.parents);
+                    }
+                }
+                removed.push(branch.git_name);
+            }
+        }
+        removed.sort();
+        removed
+    }
+    
     /// Get branches in topological sort order (parents before children)
+    /// The order is deterministic: disconnected components come out one after another,
+    /// ordered by their smallest root `BranchId`, and roots within a component are seeded
+    /// in `BranchId` order
     /// Returns an error if there are cycles in the DAG
     pub fn topological_sort(&self) -> Result<Vec<BranchId>, String> {
         let mut in_degree: HashMap<BranchId, usize> = HashMap::new();
         let mut result = Vec::new();
-        let mut queue = VecDeque::new();
-        
+        let mut visited = HashSet::new();
+
         // Initialize in-degree count for all branches
+        let mut roots = Vec::new();
         for (branch_id, branch) in &self.branches {
             in_degree.insert(*branch_id, branch.parents.len());
             if branch.parents.is_empty() {
-                queue.push_back(*branch_id);
+                roots.push(*branch_id);
             }
         }
-        
-        // Process branches with no incoming edges
-        while let Some(current_id) = queue.pop_front() {
-            result.push(current_id);
-            
-            // For each child of current branch
-            if let Some(current_branch) = self.branches.get(&current_id) {
-                for &child_id in &current_branch.children {
-                    if let Some(degree) = in_degree.get_mut(&child_id) {
-                        *degree -= 1;
-                        if *degree == 0 {
-                            queue.push_back(child_id);
+        roots.sort_by_key(|id| id.0);
+
+        for &root in &roots {
+            if visited.contains(&root) {
+                continue;
+            }
+            let component = self.connected_component(root);
+            visited.extend(component.iter().copied());
+
+            // Process branches with no incoming edges, one component at a time
+            let mut queue: VecDeque<BranchId> = roots.iter()
+                .filter(|id| component.contains(id))
+                .copied()
+                .collect();
+
+            while let Some(current_id) = queue.pop_front() {
+                result.push(current_id);
+
+                // For each child of current branch
+                if let Some(current_branch) = self.branches.get(&current_id) {
+                    for &child_id in &current_branch.children {
+                        if let Some(degree) = in_degree.get_mut(&child_id) {
+                            *degree -= 1;
+                            if *degree == 0 {
+                                queue.push_back(child_id);
+                            }
                         }
                     }
                 }
             }
         }
-        
+
         // Check for cycles
         if result.len() != self.branches.len() {
             return Err("Cycle detected in DAG - topological sort not possible".to_string());
         }
-        
+
         Ok(result)
     }
-    
+
+    /// Get `root` and its recursive children in topological order (parents before children)
+    /// Only edges inside the subtree count, so a branch that also has parents outside of it is
+    /// ordered after its parents in the subtree alone. Children are visited in `BranchId` order
+    /// Returns an error if `root` isn't in the DAG or the subtree has a cycle
+    pub fn topological_sort_from(&self, root: BranchId) -> Result<Vec<BranchId>, String> {
+        if !self.branches.contains_key(&root) {
+            return Err(format!("Branch ID {} not found in DAG", root.0));
+        }
+        let subtree = self.get_recursive_children(root);
+
+        let mut in_degree: HashMap<BranchId, usize> = subtree.iter()
+            .map(|&id| {
+                let parents = self.branches.get(&id).map_or(0, |branch| {
+                    branch.parents.iter().filter(|parent| subtree.contains(parent)).count()
+                });
+                (id, parents)
+            })
+            .collect();
+
+        // The root is the only seed; every other branch was reached from it through child edges,
+        // so the root having a parent in its own subtree means there is a cycle through it
+        if in_degree.remove(&root).is_some_and(|parents| parents > 0) {
+            return Err("Cycle detected in DAG - topological sort not possible".to_string());
+        }
+        let mut result = Vec::new();
+        let mut queue = VecDeque::from([root]);
+        while let Some(current_id) = queue.pop_front() {
+            result.push(current_id);
+
+            let mut children = self.branches.get(&current_id).map(|branch| branch.children.clone()).unwrap_or_default();
+            children.sort_by_key(|id| id.0);
+            for child_id in children {
+                if let Some(degree) = in_degree.get_mut(&child_id) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        queue.push_back(child_id);
+                    }
+                }
+            }
+        }
+
+        if result.len() != subtree.len() {
+            return Err("Cycle detected in DAG - topological sort not possible".to_string());
+        }
+
+        Ok(result)
+    }
+
+    /// Same order as topological_sort, but yielding the branches themselves
+    pub fn iter_topological(&self) -> Result<Vec<&Branch>, String> {
+        Ok(self.topological_sort()?
+            .iter()
+            .filter_map(|id| self.branches.get(id))
+            .collect())
+    }
+
+    /// Update `submit_order` to the current branches and return it
+    /// Branches keep their relative position from the last submit and new branches go after
+    /// them, so rerunning submit doesn't reshuffle PRs. Parents still always come before their
+    /// children, which can pull a new branch forward (e.g. one inserted below an existing one)
+    /// Returns an error if there are cycles in the DAG
+    pub fn reconcile_submit_order(&mut self) -> Result<Vec<BranchId>, String> {
+        // Rank by the recorded order first, then by topological order for new branches
+        let mut rank: HashMap<BranchId, usize> = HashMap::new();
+        let known = self.submit_order.iter().filter(|id| self.branches.contains_key(id));
+        for &branch_id in known.chain(self.topological_sort()?.iter()) {
+            let next_rank = rank.len();
+            rank.entry(branch_id).or_insert(next_rank);
+        }
+
+        // Kahn's algorithm, always taking the ready branch with the lowest rank
+        let mut in_degree: HashMap<BranchId, usize> = self.branches.keys().map(|id| (*id, 0)).collect();
+        for (_, child_id) in self.edges() {
+            *in_degree.entry(child_id).or_insert(0) += 1;
+        }
+        let mut ready: BinaryHeap<_> = in_degree.iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(branch_id, _)| Reverse((rank[branch_id], branch_id.0)))
+            .collect();
+
+        let mut order = Vec::new();
+        while let Some(Reverse((_, id))) = ready.pop() {
+            let branch_id = BranchId(id);
+            order.push(branch_id);
+            for child_id in &self.branches[&branch_id].children {
+                if let Some(degree) = in_degree.get_mut(child_id) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push(Reverse((rank[child_id], child_id.0)));
+                    }
+                }
+            }
+        }
+
+        self.submit_order = order.clone();
+        Ok(order)
+    }
+
+    /// Check that the stored branch IDs and counter are small enough to keep allocating new IDs
+    pub fn check_branch_ids(&self) -> Result<(), String> {
+        if self.next_branch_id > MAX_BRANCH_ID {
+            return Err(format!("next_branch_id {} is larger than the maximum of {}", self.next_branch_id, MAX_BRANCH_ID));
+        }
+        let largest = self.branches.keys()
+            .chain(self.branches.values().map(|branch| &branch.uid))
+            .map(|id| id.0)
+            .max();
+        match largest {
+            Some(id) if id > MAX_BRANCH_ID => Err(format!("Branch ID {} is larger than the maximum of {}", id, MAX_BRANCH_ID)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the DAG for structural problems and describe each one
+    /// Returns an empty list for a consistent DAG
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let name = |id: &BranchId| self.branches.get(id)
+            .map(|branch| format!("'{}'", branch.git_name))
+            .unwrap_or_else(|| format!("ID {}", id.0));
+
+        let mut ids: Vec<&BranchId> = self.branches.keys().collect();
+        ids.sort_by_key(|id| id.0);
+
+        let mut seen_names = HashSet::new();
+        for id in ids {
+            let branch = &self.branches[id];
+
+            if branch.uid != *id {
+                problems.push(format!("Branch {} is stored under ID {} but has uid {}", name(id), id.0, branch.uid.0));
+            }
+            if !seen_names.insert(&branch.git_name) {
+                problems.push(format!("Branch '{}' is tracked more than once", branch.git_name));
+            }
+
+            for parent_id in &branch.parents {
+                match self.branches.get(parent_id) {
+                    None => problems.push(format!("Branch {} references missing parent ID {}", name(id), parent_id.0)),
+                    Some(parent) if !parent.children.contains(id) => problems.push(format!(
+                        "Branch {} lists {} as a parent, but {} doesn't list it as a child",
+                        name(id), name(parent_id), name(parent_id)
+                    )),
+                    Some(_) => {}
+                }
+            }
+            for child_id in &branch.children {
+                match self.branches.get(child_id) {
+                    None => problems.push(format!("Branch {} references missing child ID {}", name(id), child_id.0)),
+                    Some(child) if !child.parents.contains(id) => problems.push(format!(
+                        "Branch {} lists {} as a child, but {} doesn't list it as a parent",
+                        name(id), name(child_id), name(child_id)
+                    )),
+                    Some(_) => {}
+                }
+            }
+
+            // topological_sort counts in-degree from `parents` but decrements it via `children`
+            let referenced_as_child = self.branches.values()
+                .map(|other| other.children.iter().filter(|&c| c == id).count())
+                .sum::<usize>();
+            if referenced_as_child != branch.parents.len() {
+                problems.push(format!(
+                    "Branch {} has {} parents but is listed as a child {} times",
+                    name(id), branch.parents.len(), referenced_as_child
+                ));
+            }
+        }
+
+        if let Err(e) = self.topological_sort() {
+            problems.push(e);
+        }
+
+        if let Some(trunk) = self.trunk.filter(|trunk| !self.branches.contains_key(trunk)) {
+            problems.push(format!("The trunk is set to missing branch ID {}", trunk.0));
+        }
+
+        problems
+    }
+
+    /// Get all branches connected to the given one, following edges in both directions
+    fn connected_component(&self, branch_id: BranchId) -> HashSet<BranchId> {
+        let mut component = HashSet::new();
+        let mut stack = vec![branch_id];
+
+        while let Some(current_id) = stack.pop() {
+            if component.insert(current_id) {
+                if let Some(branch) = self.branches.get(&current_id) {
+                    stack.extend(branch.parents.iter().copied());
+                    stack.extend(branch.children.iter().copied());
+                }
+            }
+        }
+
+        component
+    }
+
     /// Get all recursive children of a branch (including the branch itself)
     pub fn get_recursive_children(&self, branch_id: BranchId) -> HashSet<BranchId> {
         let mut visited = HashSet::new();
@@ -221,12 +753,124 @@ impl Dag {
         
         visited
     }
+
+    /// Get all recursive parents of a branch (including the branch itself)
+    pub fn get_recursive_parents(&self, branch_id: BranchId) -> HashSet<BranchId> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![branch_id];
+
+        while let Some(current_id) = stack.pop() {
+            if visited.insert(current_id) {
+                if let Some(branch) = self.branches.get(&current_id) {
+                    stack.extend(branch.parents.iter().copied());
+                }
+            }
+        }
+
+        visited
+    }
+
+    /// Copy a branch and its recursive children into a new DAG, naming each copy with `rename`
+    /// The copies get fresh ids and the same edges among themselves, in the same order, so primary
+    /// parents carry over; edges leaving the subtree are dropped, so `root` becomes a root
+    /// PR numbers, notes and failed rebases belong to the original branches and aren't copied
+    pub fn clone_subtree(&self, root: BranchId, rename: impl Fn(&str) -> String) -> Dag {
+        let subtree = self.get_recursive_children(root);
+        // Copies are numbered in the order of the originals
+        let mut order: Vec<BranchId> = subtree.into_iter().collect();
+        order.sort_by_key(|id| id.0);
+
+        let mut clone = Dag::new();
+        let mut new_ids = HashMap::new();
+        for id in &order {
+            let Some(branch) = self.branches.get(id) else {
+                continue;
+            };
+            new_ids.insert(*id, clone.create_branch(rename(&branch.git_name)));
+        }
+
+        let map_ids = |ids: &[BranchId]| -> Vec<BranchId> {
+            ids.iter().filter_map(|id| new_ids.get(id).cloned()).collect()
+        };
+        for (id, new_id) in &new_ids {
+            let branch = &self.branches[id];
+            let (parents, children) = (map_ids(&branch.parents), map_ids(&branch.children));
+            if let Some(copy) = clone.get_branch_mut(new_id) {
+                copy.parents = parents;
+                copy.children = children;
+            }
+        }
+
+        clone
+    }
+
+    /// Length of the longest chain of parents above a branch: 0 for a root, 1 for its children...
+    /// Returns None if the branch isn't in the DAG or the DAG has a cycle
+    pub fn get_branch_depth(&self, branch_id: BranchId) -> Option<usize> {
+        self.branch_depths()?.get(&branch_id).copied()
+    }
+
+    /// The depth of every branch, as computed by `get_branch_depth`, in a single pass
+    /// Returns None if the DAG has a cycle
+    pub fn branch_depths(&self) -> Option<HashMap<BranchId, usize>> {
+        // Parents come first in topological order, so their depths are always known
+        let mut depths: HashMap<BranchId, usize> = HashMap::new();
+        for id in self.topological_sort().ok()? {
+            let depth = self.branches[&id].parents.iter()
+                .filter_map(|parent_id| depths.get(parent_id))
+                .map(|depth| depth + 1)
+                .max()
+                .unwrap_or(0);
+            depths.insert(id, depth);
+        }
+        Some(depths)
+    }
+
+    /// Count the branches in each status, as computed by `resolve_status`
+    /// Statuses no branch is in are left out
+    pub fn status_counts(&self, resolve_status: impl Fn(&Branch) -> BranchStatus) -> HashMap<BranchStatus, usize> {
+        let mut counts = HashMap::new();
+        for branch in self.branches.values() {
+            *counts.entry(resolve_status(branch)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Whether `ancestor` is a strict ancestor of `descendant` according to the recorded edges
+    /// This only looks at the DAG, which can disagree with the commit graph (e.g. before a rebase)
+    pub fn is_ancestor_in_dag(&self, ancestor: BranchId, descendant: BranchId) -> bool {
+        ancestor != descendant && self.get_recursive_children(ancestor).contains(&descendant)
+    }
+}
+
+/// Replace `old` in `ids` with `replacements`, in place and without introducing duplicates
+fn splice(ids: &mut Vec<BranchId>, old: BranchId, replacements: &[BranchId]) {
+    let Some(position) = ids.iter().position(|&id| id == old) else {
+        return;
+    };
+    ids.remove(position);
+    let new_ids: Vec<BranchId> = replacements.iter()
+        .filter(|id| !ids.contains(id))
+        .cloned()
+        .collect();
+    ids.splice(position..position, new_ids);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_branch_new() {
+        let branch = Branch::new(BranchId(5), "feature");
+        assert_eq!(branch, Branch::with_id(BranchId(5), "feature".to_string()));
+        assert!(branch.parents.is_empty() && branch.children.is_empty());
+
+        let mut dag = Dag::default();
+        dag.insert_branch(branch).unwrap();
+        assert_eq!(dag.create_branch("next".to_string()), BranchId(6));
+    }
+
     #[test]
     fn test_unique_id_generation() {
         let mut dag = Dag::new();
@@ -258,11 +902,16 @@ mod tests {
         
         // Insert a branch with a high ID
         let high_id_branch = Branch::with_id(BranchId(100), "external".to_string());
-        dag.insert_branch(high_id_branch);
+        dag.insert_branch(high_id_branch).unwrap();
         
         // Next created branch should have ID 101, not 1
         let new_id = dag.create_branch("new_branch".to_string());
         assert_eq!(new_id.0, 101);
+
+        // An ID too large to keep allocating above is rejected instead of overflowing
+        assert!(dag.insert_branch(Branch::with_id(BranchId(usize::MAX), "huge".to_string())).is_err());
+        assert!(dag.get_branch(&BranchId(usize::MAX)).is_none());
+        assert_eq!(dag.next_branch_id, 102);
     }
     
     #[test]
@@ -288,6 +937,29 @@ mod tests {
         assert!(restored_dag.get_branch(&BranchId(3)).is_some());
     }
 
+    #[test]
+    fn test_edges_of_diamond() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feat1_id = dag.create_branch("feat1".to_string());
+        let feat2_id = dag.create_branch("feat2".to_string());
+        let merge_id = dag.create_branch("merge".to_string());
+        dag.add_parent_child_relationship("feat1", "main").unwrap();
+        dag.add_parent_child_relationship("feat2", "main").unwrap();
+        dag.add_parent_child_relationship("merge", "feat1").unwrap();
+        dag.add_parent_child_relationship("merge", "feat2").unwrap();
+
+        let edges: HashSet<(BranchId, BranchId)> = dag.edges().collect();
+        let expected = HashSet::from([
+            (main_id, feat1_id),
+            (main_id, feat2_id),
+            (feat1_id, merge_id),
+            (feat2_id, merge_id),
+        ]);
+        assert_eq!(edges, expected);
+        assert_eq!(dag.edges().count(), 4);
+    }
+
     #[test]
     fn test_topological_sort_empty_dag() {
         let dag = Dag::new();
@@ -394,6 +1066,476 @@ mod tests {
         assert!(result.unwrap_err().contains("Cycle detected"));
     }
 
+    #[test]
+    fn test_set_primary_parent() {
+        let mut dag = Dag::new();
+        let feat1_id = dag.create_branch("feat1".to_string());
+        let feat2_id = dag.create_branch("feat2".to_string());
+        let feat3_id = dag.create_branch("feat3".to_string());
+        let merge_id = dag.create_branch("merge".to_string());
+
+        dag.add_parent_child_relationship("merge", "feat1").unwrap();
+        dag.add_parent_child_relationship("merge", "feat2").unwrap();
+        dag.add_parent_child_relationship("merge", "feat3").unwrap();
+
+        dag.set_primary_parent(merge_id, feat3_id).unwrap();
+        assert_eq!(dag.get_branch(&merge_id).unwrap().parents, vec![feat3_id, feat1_id, feat2_id]);
+
+        // Already primary is a no-op
+        dag.set_primary_parent(merge_id, feat3_id).unwrap();
+        assert_eq!(dag.get_branch(&merge_id).unwrap().parents, vec![feat3_id, feat1_id, feat2_id]);
+    }
+
+    #[test]
+    fn test_set_primary_parent_not_a_parent() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+
+        let result = dag.set_primary_parent(feature_id, main_id);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("is not a parent"));
+
+        let result = dag.set_primary_parent(BranchId(999), main_id);
+        assert!(result.unwrap_err().contains("not found in DAG"));
+    }
+
+    #[test]
+    fn test_create_branch_ignores_stale_counter() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+
+        // Simulate a hand-edited file whose counter is behind the existing IDs
+        dag.next_branch_id = 1;
+        let new_id = dag.create_branch("new".to_string());
+
+        assert_ne!(new_id, main_id);
+        assert_ne!(new_id, feature_id);
+        assert_eq!(dag.len(), 3);
+        assert_eq!(dag.get_branch(&main_id).unwrap().git_name, "main");
+
+        // Removed IDs are still not reused
+        dag.remove_branch(&new_id);
+        assert_ne!(dag.create_branch("another".to_string()), new_id);
+    }
+
+    #[test]
+    fn test_reparent() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feat1_id = dag.create_branch("feat1".to_string());
+        let feat2_id = dag.create_branch("feat2".to_string());
+        let child_id = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(feat1_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(feat2_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(child_id, feat2_id).unwrap();
+
+        dag.reparent(feat2_id, feat1_id).unwrap();
+
+        assert_eq!(dag.get_branch(&feat2_id).unwrap().parents, vec![feat1_id]);
+        assert_eq!(dag.get_branch(&feat1_id).unwrap().children, vec![feat2_id]);
+        assert_eq!(dag.get_branch(&main_id).unwrap().children, vec![feat1_id]);
+        // The moved branch keeps its own children
+        assert_eq!(dag.get_branch(&child_id).unwrap().parents, vec![feat2_id]);
+    }
+
+    #[test]
+    fn test_detach() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+        let child_id = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(feature_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(child_id, feature_id).unwrap();
+
+        dag.detach(feature_id).unwrap();
+
+        let feature = dag.get_branch(&feature_id).unwrap();
+        assert!(feature.parents.is_empty() && feature.children.is_empty());
+        assert!(dag.get_branch(&main_id).unwrap().children.is_empty());
+        assert!(dag.get_branch(&child_id).unwrap().parents.is_empty());
+        assert!(dag.detach(BranchId(42)).is_err());
+    }
+
+    #[test]
+    fn test_reparent_under_descendant_fails() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+        dag.add_parent_child_relationship_by_id(feature_id, main_id).unwrap();
+        let before = dag.clone();
+
+        assert!(dag.reparent(main_id, feature_id).is_err());
+        assert!(dag.reparent(main_id, main_id).is_err());
+        assert_eq!(dag, before, "A rejected reparent should not change the DAG");
+    }
+
+    #[test]
+    fn test_merge_into() {
+        // main -> a -> a1
+        //      -> b -> b1
+        //           -> b2
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let a_id = dag.create_branch("a".to_string());
+        let a1_id = dag.create_branch("a1".to_string());
+        let b_id = dag.create_branch("b".to_string());
+        let b1_id = dag.create_branch("b1".to_string());
+        let b2_id = dag.create_branch("b2".to_string());
+        dag.add_parent_child_relationship_by_id(a_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(a1_id, a_id).unwrap();
+        dag.add_parent_child_relationship_by_id(b_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(b1_id, b_id).unwrap();
+        dag.add_parent_child_relationship_by_id(b2_id, b_id).unwrap();
+
+        dag.merge_into(b_id, a_id).unwrap();
+
+        assert!(dag.get_branch(&b_id).is_none());
+        assert_eq!(dag.len(), 5);
+        assert_eq!(dag.get_branch(&main_id).unwrap().children, vec![a_id]);
+        assert_eq!(dag.get_branch(&a_id).unwrap().children, vec![a1_id, b1_id, b2_id]);
+        assert_eq!(dag.get_branch(&b1_id).unwrap().parents, vec![a_id]);
+        assert_eq!(dag.get_branch(&b2_id).unwrap().parents, vec![a_id]);
+        assert!(dag.topological_sort().is_ok());
+    }
+
+    #[test]
+    fn test_merge_into_descendant_fails() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let a_id = dag.create_branch("a".to_string());
+        let a1_id = dag.create_branch("a1".to_string());
+        dag.add_parent_child_relationship_by_id(a_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(a1_id, a_id).unwrap();
+        let before = dag.clone();
+
+        assert!(dag.merge_into(a_id, a1_id).is_err());
+        assert!(dag.merge_into(a_id, a_id).is_err());
+        assert!(dag.merge_into(BranchId(999), a_id).is_err());
+        assert_eq!(dag, before, "A rejected merge should not change the DAG");
+    }
+
+    #[test]
+    fn test_insert_between() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let other = dag.create_branch("other".to_string());
+        let child = dag.create_branch("child".to_string());
+        let new = dag.create_branch("new".to_string());
+        dag.add_parent_child_relationship_by_id(child, other).unwrap();
+        dag.add_parent_child_relationship_by_id(child, main).unwrap();
+
+        dag.insert_between(new, main, child).unwrap();
+
+        assert_eq!(dag.get_branch(&main).unwrap().children, vec![new]);
+        assert_eq!(dag.get_branch(&new).unwrap().parents, vec![main]);
+        assert_eq!(dag.get_branch(&new).unwrap().children, vec![child]);
+        // new replaces main in place, leaving the primary parent (other) first
+        assert_eq!(dag.get_branch(&child).unwrap().parents, vec![other, new]);
+        assert!(dag.validate().is_empty());
+    }
+
+    #[test]
+    fn test_insert_between_rejects_invalid_edges() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let child = dag.create_branch("child".to_string());
+        let grandchild = dag.create_branch("grandchild".to_string());
+        let new = dag.create_branch("new".to_string());
+        dag.add_parent_child_relationship_by_id(child, main).unwrap();
+        dag.add_parent_child_relationship_by_id(grandchild, child).unwrap();
+        let before = dag.clone();
+
+        assert!(dag.insert_between(new, main, grandchild).unwrap_err().contains("is not a parent"));
+        assert!(dag.insert_between(grandchild, main, child).unwrap_err().contains("cycle"));
+        assert!(dag.insert_between(child, main, child).unwrap_err().contains("cycle"));
+        assert!(dag.insert_between(BranchId(999), main, child).is_err());
+        assert_eq!(dag, before, "A rejected insert should not change the DAG");
+    }
+
+    #[test]
+    fn test_retain_existing_relinks_around_pruned_branches() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let other = dag.create_branch("other".to_string());
+        let middle = dag.create_branch("middle".to_string());
+        let child = dag.create_branch("child".to_string());
+        let gone = dag.create_branch("gone".to_string());
+        dag.add_parent_child_relationship_by_id(middle, main).unwrap();
+        dag.add_parent_child_relationship_by_id(child, middle).unwrap();
+        dag.add_parent_child_relationship_by_id(child, other).unwrap();
+        dag.add_parent_child_relationship_by_id(gone, child).unwrap();
+
+        let existing: HashSet<String> = ["main", "other", "child"].iter().map(|s| s.to_string()).collect();
+        let removed = dag.retain_existing(&existing);
+
+        assert_eq!(removed, vec!["gone".to_string(), "middle".to_string()]);
+        assert_eq!(dag.len(), 3);
+        // main takes middle's place, so it stays the primary parent
+        assert_eq!(dag.get_branch(&child).unwrap().parents, vec![main, other]);
+        assert_eq!(dag.get_branch(&main).unwrap().children, vec![child]);
+        assert!(dag.get_branch(&child).unwrap().children.is_empty());
+        assert!(dag.validate().is_empty());
+
+        // Nothing left to prune
+        assert!(dag.retain_existing(&existing).is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_submit_order_keeps_previous_order() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let first = dag.create_branch("first".to_string());
+        let second = dag.create_branch("second".to_string());
+        dag.add_parent_child_relationship_by_id(first, main).unwrap();
+        dag.add_parent_child_relationship_by_id(second, main).unwrap();
+
+        // A previous submit went through second before first
+        dag.submit_order = vec![main, second, first];
+        assert_eq!(dag.reconcile_submit_order().unwrap(), vec![main, second, first]);
+
+        // New branches go after the existing ones, in topological order among themselves
+        let third = dag.create_branch("third".to_string());
+        dag.add_parent_child_relationship_by_id(third, second).unwrap();
+        let fourth = dag.create_branch("fourth".to_string());
+        dag.add_parent_child_relationship_by_id(fourth, first).unwrap();
+        assert_eq!(dag.reconcile_submit_order().unwrap(), vec![main, second, first, fourth, third]);
+        assert_eq!(dag.submit_order, vec![main, second, first, fourth, third]);
+
+        // Removed branches drop out, and a new branch goes before its existing child, which
+        // has to wait for it
+        dag.retain_existing(&["main", "second", "first", "fourth"].iter().map(|s| s.to_string()).collect());
+        let below = dag.create_branch("below".to_string());
+        dag.insert_between(below, main, second).unwrap();
+        assert_eq!(dag.reconcile_submit_order().unwrap(), vec![main, first, fourth, below, second]);
+    }
+
+    #[test]
+    fn test_clone_subtree_copies_diamond() {
+        // main -> a, b -> merge (primary parent b), plus an unrelated side branch
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let a = dag.create_branch("a".to_string());
+        let b = dag.create_branch("b".to_string());
+        let merge = dag.create_branch("merge".to_string());
+        let side = dag.create_branch("side".to_string());
+        dag.add_parent_child_relationship_by_id(a, main).unwrap();
+        dag.add_parent_child_relationship_by_id(b, main).unwrap();
+        dag.add_parent_child_relationship_by_id(merge, b).unwrap();
+        dag.add_parent_child_relationship_by_id(merge, a).unwrap();
+        dag.add_parent_child_relationship_by_id(side, a).unwrap();
+        dag.get_branch_mut(&a).unwrap().pr_number = Some(3);
+
+        let clone = dag.clone_subtree(main, |name| format!("fork/{}", name));
+
+        assert_eq!(clone.len(), 5);
+        let id_of = |name: &str| clone.find_branch_by_name(name).unwrap().uid;
+        let parents_of = |name: &str| {
+            clone.get_branch(&id_of(name)).unwrap().parents.iter()
+                .map(|id| clone.get_branch(id).unwrap().git_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(parents_of("fork/main").is_empty());
+        assert_eq!(parents_of("fork/a"), vec!["fork/main"]);
+        assert_eq!(parents_of("fork/b"), vec!["fork/main"]);
+        assert_eq!(parents_of("fork/merge"), vec!["fork/b", "fork/a"]);
+        assert_eq!(parents_of("fork/side"), vec!["fork/a"]);
+        assert_eq!(clone.get_branch(&id_of("fork/a")).unwrap().children.len(), 2);
+        assert_eq!(clone.get_branch(&id_of("fork/a")).unwrap().pr_number, None);
+        assert!(clone.validate().is_empty());
+
+        // Copying from a middle branch leaves out its parents and siblings
+        let partial = dag.clone_subtree(b, |name| format!("{}-copy", name));
+        assert_eq!(partial.get_tracked_branch_names().len(), 2);
+        let b_copy = partial.find_branch_by_name("b-copy").unwrap();
+        assert!(b_copy.parents.is_empty());
+        let merge_copy = partial.find_branch_by_name("merge-copy").unwrap();
+        assert_eq!(merge_copy.parents, vec![b_copy.uid]);
+
+        // The original is untouched
+        assert_eq!(dag.len(), 5);
+        assert!(dag.find_branch_by_name("fork/main").is_none());
+    }
+
+    #[test]
+    fn test_trunk_follows_removed_and_merged_branches() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let develop = dag.create_branch("develop".to_string());
+
+        dag.trunk = Some(develop);
+        assert_eq!(dag.trunk_branch().map(|b| b.git_name.as_str()), Some("develop"));
+
+        dag.merge_into(develop, main).unwrap();
+        assert_eq!(dag.trunk, Some(main));
+
+        dag.remove_branch(&main);
+        assert_eq!(dag.trunk, None);
+
+        // A trunk that isn't tracked (e.g. after a hand edit) is reported by validate
+        dag.trunk = Some(BranchId(42));
+        assert!(dag.trunk_branch().is_none());
+        assert_eq!(dag.validate(), vec!["The trunk is set to missing branch ID 42".to_string()]);
+    }
+
+    #[test]
+    fn test_get_branch_depth_follows_longest_chain() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let feature = dag.create_branch("feature".to_string());
+        let fix = dag.create_branch("fix".to_string());
+        let merged = dag.create_branch("merged".to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        dag.add_parent_child_relationship_by_id(fix, feature).unwrap();
+        // merged hangs directly under main too, but its longest chain goes through fix
+        dag.add_parent_child_relationship_by_id(merged, main).unwrap();
+        dag.add_parent_child_relationship_by_id(merged, fix).unwrap();
+
+        assert_eq!(dag.get_branch_depth(main), Some(0));
+        assert_eq!(dag.get_branch_depth(feature), Some(1));
+        assert_eq!(dag.get_branch_depth(fix), Some(2));
+        assert_eq!(dag.get_branch_depth(merged), Some(3));
+        assert_eq!(dag.get_branch_depth(BranchId(999)), None);
+        assert_eq!(dag.branch_depths().and_then(|depths| depths.into_values().max()), Some(3));
+    }
+
+    #[test]
+    fn test_status_counts_with_stub_resolver() {
+        let mut dag = Dag::new();
+        dag.create_branch("main".to_string());
+        dag.create_branch("feature".to_string());
+        dag.create_branch("fix".to_string());
+        dag.create_branch("broken".to_string());
+
+        let counts = dag.status_counts(|branch| match branch.git_name.as_str() {
+            "main" => BranchStatus::NoParent,
+            "broken" => BranchStatus::FailedUpdate,
+            _ => BranchStatus::OutOfDate,
+        });
+
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[&BranchStatus::NoParent], 1);
+        assert_eq!(counts[&BranchStatus::OutOfDate], 2);
+        assert_eq!(counts[&BranchStatus::FailedUpdate], 1);
+        assert!(!counts.contains_key(&BranchStatus::UpToDate));
+        assert!(Dag::new().status_counts(|_| BranchStatus::Unknown).is_empty());
+    }
+
+    #[test]
+    fn test_find_branch_by_name_mut() {
+        let mut dag = Dag::new();
+        let feature_id = dag.create_branch("feature".to_string());
+
+        dag.find_branch_by_name_mut("feature").expect("feature is tracked").pr_number = Some(7);
+        assert_eq!(dag.get_branch(&feature_id).unwrap().pr_number, Some(7));
+        assert!(dag.find_branch_by_name_mut("missing").is_none());
+    }
+
+    #[test]
+    fn test_is_ancestor_in_dag() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let feature = dag.create_branch("feature".to_string());
+        let fix = dag.create_branch("fix".to_string());
+        let other = dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        dag.add_parent_child_relationship_by_id(fix, feature).unwrap();
+
+        assert!(dag.is_ancestor_in_dag(main, feature));
+        assert!(dag.is_ancestor_in_dag(main, fix));
+        assert!(!dag.is_ancestor_in_dag(fix, main));
+        assert!(!dag.is_ancestor_in_dag(main, main), "A branch is not its own ancestor");
+        assert!(!dag.is_ancestor_in_dag(main, other));
+        assert!(!dag.is_ancestor_in_dag(main, BranchId(999)));
+    }
+
+    #[test]
+    fn test_add_relationship_rejects_cycles() {
+        let mut dag = Dag::new();
+        let a = dag.create_branch("a".to_string());
+        let b = dag.create_branch("b".to_string());
+        let c = dag.create_branch("c".to_string());
+        dag.add_parent_child_relationship_by_id(b, a).unwrap();
+        dag.add_parent_child_relationship_by_id(c, b).unwrap();
+        let before = dag.clone();
+
+        assert!(dag.add_parent_child_relationship_by_id(a, c).unwrap_err().contains("cycle"));
+        assert!(dag.add_parent_child_relationship_by_id(a, a).unwrap_err().contains("cycle"));
+        assert!(dag.add_parent_child_relationship("a", "b").unwrap_err().contains("cycle"));
+        assert_eq!(dag, before, "A rejected edge should not change the DAG");
+
+        // Re-adding an existing edge is still fine
+        assert!(dag.add_parent_child_relationship("b", "a").is_ok());
+        assert!(dag.topological_sort().is_ok());
+    }
+
+    #[test]
+    fn test_iter_topological_matches_id_order() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let feature = dag.create_branch("feature".to_string());
+        let fix = dag.create_branch("fix".to_string());
+        let other = dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        dag.add_parent_child_relationship_by_id(fix, feature).unwrap();
+        dag.add_parent_child_relationship_by_id(fix, other).unwrap();
+
+        let ids = dag.topological_sort().unwrap();
+        let branch_ids: Vec<BranchId> = dag.iter_topological().unwrap()
+            .iter()
+            .map(|branch| branch.uid)
+            .collect();
+        assert_eq!(branch_ids, ids);
+    }
+
+    #[test]
+    fn test_topological_sort_disconnected_components() {
+        // Two independent chains a -> b and c -> d, created interleaved
+        fn build() -> (Dag, [BranchId; 4]) {
+            let mut dag = Dag::new();
+            let a = dag.create_branch("a".to_string());
+            let c = dag.create_branch("c".to_string());
+            let b = dag.create_branch("b".to_string());
+            let d = dag.create_branch("d".to_string());
+            dag.add_parent_child_relationship_by_id(b, a).unwrap();
+            dag.add_parent_child_relationship_by_id(d, c).unwrap();
+            (dag, [a, b, c, d])
+        }
+
+        let (dag, [a, b, c, d]) = build();
+        let sorted = dag.topological_sort().unwrap();
+        let position = |id: BranchId| sorted.iter().position(|&x| x == id).unwrap();
+        assert!(position(a) < position(b));
+        assert!(position(c) < position(d));
+
+        // Components are grouped and ordered by their root's ID
+        assert_eq!(sorted, vec![a, b, c, d]);
+
+        // Fresh DAGs get fresh HashMap seeds, the order must not depend on them
+        for _ in 0..20 {
+            let (dag, _) = build();
+            assert_eq!(dag.topological_sort().unwrap(), sorted);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+        dag.add_parent_child_relationship_by_id(feature_id, main_id).unwrap();
+        assert!(dag.validate().is_empty(), "A consistent DAG has no problems: {:?}", dag.validate());
+
+        // A one-sided link, as left behind by a crash between the two pushes
+        let orphan_id = dag.create_branch("orphan".to_string());
+        dag.get_branch_mut(&orphan_id).unwrap().parents.push(main_id);
+
+        let problems = dag.validate();
+        assert!(problems.iter().any(|p| p.contains("'orphan' lists 'main' as a parent")), "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("'orphan' has 1 parents but is listed as a child 0 times")), "{:?}", problems);
+    }
+
     #[test]
     fn test_get_recursive_children_no_children() {
         let mut dag = Dag::new();
@@ -458,6 +1600,26 @@ mod tests {
         assert!(children_from_feat3.contains(&feat3_id));
     }
 
+    #[test]
+    fn test_get_recursive_parents_follows_every_parent() {
+        let mut dag = Dag::new();
+
+        // main -> feat1 -> merged <- feat2 <- main, and feat1 -> other
+        let main_id = dag.create_branch("main".to_string());
+        let feat1_id = dag.create_branch("feat1".to_string());
+        let feat2_id = dag.create_branch("feat2".to_string());
+        let merged_id = dag.create_branch("merged".to_string());
+        dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship("feat1", "main").unwrap();
+        dag.add_parent_child_relationship("feat2", "main").unwrap();
+        dag.add_parent_child_relationship("merged", "feat1").unwrap();
+        dag.add_parent_child_relationship("merged", "feat2").unwrap();
+        dag.add_parent_child_relationship("other", "feat1").unwrap();
+
+        assert_eq!(dag.get_recursive_parents(merged_id), HashSet::from([merged_id, feat1_id, feat2_id, main_id]));
+        assert_eq!(dag.get_recursive_parents(main_id), HashSet::from([main_id]));
+    }
+
     #[test]
     fn test_get_recursive_children_complex_tree() {
         let mut dag = Dag::new();
@@ -506,5 +1668,49 @@ mod tests {
         assert!(children_from_feat2.contains(&feat2_id));
         assert!(children_from_feat2.contains(&sub3_id));
     }
+
+    #[test]
+    fn test_topological_sort_from_intermediate_node() {
+        let mut dag = Dag::new();
+
+        // Same tree as above, plus sub4 also having feat2 as a parent:
+        //       main
+        //      /    \
+        //   feat1   feat2
+        //   /  \    |   \
+        // sub1 sub2  sub3 |
+        //       |         |
+        //     sub4 -------+
+        let main_id = dag.create_branch("main".to_string());
+        let feat1_id = dag.create_branch("feat1".to_string());
+        let feat2_id = dag.create_branch("feat2".to_string());
+        let sub1_id = dag.create_branch("sub1".to_string());
+        let sub2_id = dag.create_branch("sub2".to_string());
+        let sub3_id = dag.create_branch("sub3".to_string());
+        let sub4_id = dag.create_branch("sub4".to_string());
+
+        dag.add_parent_child_relationship("feat1", "main").unwrap();
+        dag.add_parent_child_relationship("feat2", "main").unwrap();
+        dag.add_parent_child_relationship("sub1", "feat1").unwrap();
+        dag.add_parent_child_relationship("sub2", "feat1").unwrap();
+        dag.add_parent_child_relationship("sub3", "feat2").unwrap();
+        dag.add_parent_child_relationship("sub4", "sub2").unwrap();
+        dag.add_parent_child_relationship("sub4", "feat2").unwrap();
+
+        // feat2 is outside feat1's subtree, so it doesn't hold sub4 back
+        assert_eq!(dag.topological_sort_from(feat1_id), Ok(vec![feat1_id, sub1_id, sub2_id, sub4_id]));
+
+        // From feat2, sub4 is in the subtree even though its other parent isn't
+        assert_eq!(dag.topological_sort_from(feat2_id), Ok(vec![feat2_id, sub3_id, sub4_id]));
+        assert_eq!(dag.topological_sort_from(sub4_id), Ok(vec![sub4_id]));
+
+        // From the root, every branch comes after all of its parents
+        let order = dag.topological_sort_from(main_id).unwrap();
+        assert_eq!(order.len(), 7);
+        let position = |id: BranchId| order.iter().position(|&other| other == id).unwrap();
+        assert!(position(sub4_id) > position(sub2_id) && position(sub4_id) > position(feat2_id));
+
+        assert!(dag.topological_sort_from(BranchId(99)).is_err());
+    }
 }
 
@@ -1,5 +1,5 @@
 use super::utils::{FlowTest, FlowTestWithOrigin, TestCommand, run_flow_test, run_flow_test_with_origin};
-use crate::dag::Dag;
+use crate::dag::{Branch, BranchId, Dag, FailedRebase};
 
 #[test]
 #[serial_test::serial]
@@ -138,7 +138,10 @@ fn test_update_command_with_local_origin() {
             // === Setup in clone repo ===
             // Track branches in dagit
             TestCommand::dagit_ok(&["track", "main"]),
-            TestCommand::dagit_ok(&["track", "feature"]),
+            // feature only exists on origin, so it's only created locally when asked to
+            TestCommand::dagit_fail(&["track", "feature"])
+                .with_output_containing("does not exist locally"),
+            TestCommand::dagit_ok(&["track", "feature", "--from-origin"]),
             
             // Create local changes that need rebasing
             TestCommand::git_ok(&["checkout", "main"]),
@@ -232,3 +235,1476 @@ fn test_redundant_branch_detection() {
     run_flow_test_with_origin(test).expect("Redundant branch detection should work");
 }
 
+
+#[test]
+#[serial_test::serial]
+fn test_set_primary_parent_command() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main")
+        .expect("Failed to add parent-child relationship");
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            // main already is feature's only parent
+            TestCommand::dagit_ok(&["set-primary", "feature", "main"]),
+            // feature is not a parent of main
+            TestCommand::dagit_fail(&["set-primary", "main", "feature"]),
+            // untracked branches are rejected
+            TestCommand::dagit_fail(&["set-primary", "feature", "nope"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("set-primary flow test should succeed");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_nonexistent_branch_fails() {
+    // A typo'd branch name must not end up in the DAG
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::dagit_fail(&["track", "mian"]),
+            // Revision-like names that aren't local branches are rejected too
+            TestCommand::dagit_fail(&["track", "HEAD"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("Tracking a nonexistent branch should fail without changing the DAG");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_onto_ref_rebases_roots() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Origin commit"]),
+        ])
+        .with_clone_commands(vec![
+            // A tagged commit that main doesn't contain yet
+            TestCommand::git_ok(&["checkout", "-b", "side"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Release commit"]),
+            TestCommand::git_ok(&["tag", "release"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::dagit_ok(&["track", "main"]),
+
+            // Unresolvable refs are rejected up front
+            TestCommand::dagit_fail(&["update", "--onto", "no-such-ref"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "release", "main"]),
+
+            TestCommand::dagit_ok(&["update", "--onto", "release"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "release", "main"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update --onto should rebase root branches onto the ref");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_confirm_declined_skips_relationships() {
+    // Flow test commands have no stdin, so every confirmation prompt is declined
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature", "--confirm"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("Declined confirmations should leave the branch unlinked");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_failed_rebase_is_persisted() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    let feature_id = expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main")
+        .expect("Failed to add parent-child relationship");
+    expected_dag.get_branch_mut(&feature_id).unwrap().last_failed_rebase = Some(FailedRebase::new("main", ""));
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+
+            // Renaming the same file differently on both branches makes the rebase conflict
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["mv", "README.md", "FEATURE.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on feature"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "MAIN.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on main"]),
+
+            TestCommand::dagit_ok(&["failed"]),
+            TestCommand::dagit_fail(&["update"])
+                .with_output_containing("failed with conflicts in: FEATURE.md, MAIN.md, README.md")
+                .with_output_containing("'main' was at "),
+            TestCommand::dagit_fail(&["failed"])
+                .with_output_containing("feature (failed to rebase onto 'main@"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("A conflicting update should record the failed rebase and list the conflicting files");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_move_rebases_subtree_onto_new_parent() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature-a".to_string());
+    expected_dag.create_branch("feature-b".to_string());
+    expected_dag.create_branch("child-b".to_string());
+    expected_dag.add_parent_child_relationship("feature-a", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature-b", "feature-a").unwrap();
+    expected_dag.add_parent_child_relationship("child-b", "feature-b").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-a"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature A"]),
+            TestCommand::dagit_ok(&["track", "feature-a"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-b", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature B"]),
+            TestCommand::dagit_ok(&["track", "feature-b"]),
+            TestCommand::git_ok(&["checkout", "-b", "child-b"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child of B"]),
+            TestCommand::dagit_ok(&["track", "child-b"]),
+
+            // A branch can't be moved under its own descendant
+            TestCommand::dagit_fail(&["move", "feature-b", "--onto", "child-b"]),
+
+            TestCommand::dagit_ok(&["move", "feature-b", "--onto", "feature-a"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "feature-a", "feature-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "feature-a", "child-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "feature-b", "child-b"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("move should rebase the subtree and update the DAG");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_move_conflict_keeps_original_edges() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature-a".to_string());
+    expected_dag.create_branch("feature-b".to_string());
+    expected_dag.add_parent_child_relationship("feature-a", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature-b", "main").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-a"]),
+            TestCommand::git_ok(&["mv", "README.md", "A.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on A"]),
+            TestCommand::dagit_ok(&["track", "feature-a"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-b", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "B.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on B"]),
+            TestCommand::dagit_ok(&["track", "feature-b"]),
+
+            TestCommand::dagit_fail(&["move", "feature-b", "--onto", "feature-a"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("A conflicting move should leave the DAG unchanged");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_move_conflict_in_descendant_restores_original_state() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature-a".to_string());
+    expected_dag.create_branch("feature-b".to_string());
+    expected_dag.create_branch("child-b".to_string());
+    expected_dag.add_parent_child_relationship("feature-a", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature-b", "main").unwrap();
+    expected_dag.add_parent_child_relationship("child-b", "feature-b").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-a"]),
+            TestCommand::git_ok(&["mv", "README.md", "A.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on A"]),
+            TestCommand::dagit_ok(&["track", "feature-a"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-b", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature B"]),
+            TestCommand::dagit_ok(&["track", "feature-b"]),
+            TestCommand::git_ok(&["tag", "original-feature-b"]),
+            TestCommand::git_ok(&["checkout", "-b", "child-b"]),
+            TestCommand::git_ok(&["mv", "README.md", "B.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on child of B"]),
+            TestCommand::dagit_ok(&["track", "child-b"]),
+            TestCommand::git_ok(&["tag", "original-child-b"]),
+
+            // feature-b moves cleanly but child-b conflicts, so the whole move is undone
+            TestCommand::dagit_fail(&["move", "feature-b", "--onto", "feature-a"])
+                .with_output_containing("Move aborted: rebasing 'child-b' onto 'feature-b' failed"),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "feature-a", "feature-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "feature-b", "original-feature-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "original-feature-b", "feature-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "child-b", "original-child-b"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "original-child-b", "child-b"]),
+            TestCommand::git_ok(&["diff", "--exit-code"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("A move that fails part way should restore the original branches and DAG");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_current_command() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["current"]),
+
+            // An untracked branch is reported rather than treated as an error
+            TestCommand::git_ok(&["checkout", "-b", "untracked"]),
+            TestCommand::dagit_ok(&["current"]),
+        ]);
+
+    run_flow_test(test).expect("current should work on tracked and untracked branches");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_refs_rebases_linear_stack() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature-a".to_string());
+    expected_dag.create_branch("feature-b".to_string());
+    expected_dag.add_parent_child_relationship("feature-a", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature-b", "feature-a").unwrap();
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-a"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature A"]),
+            TestCommand::dagit_ok(&["track", "feature-a"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature-b"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature B"]),
+            TestCommand::dagit_ok(&["track", "feature-b"]),
+
+            // Move main forward so the whole stack needs rebasing
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Main moves on"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "feature-a"]),
+
+            TestCommand::dagit_ok(&["update", "--update-refs"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "feature-a"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "feature-a", "feature-b"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("update --update-refs should move the whole stack");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_dag_parents_and_children_queries() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            TestCommand::dagit_ok(&["dag", "--parents-of", "feature"]),
+            TestCommand::dagit_ok(&["dag", "--children-of", "main"]),
+            TestCommand::dagit_fail(&["dag", "--parents-of", "untracked"]),
+            TestCommand::dagit_fail(&["dag", "--parents-of", "feature", "--children-of", "main"]),
+        ]);
+
+    run_flow_test(test).expect("dag queries should succeed for tracked branches only");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_fast_forwards_trunk() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            // Let the clone push to the checked out branch of origin
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+
+            // Someone else pushes to origin/main
+            TestCommand::git_ok(&["checkout", "-b", "remote-work"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Remote work"]),
+            TestCommand::git_ok(&["push", "origin", "remote-work:main"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+
+            TestCommand::dagit_ok(&["update"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "remote-work", "main"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "remote-work"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update should fast-forward the trunk to origin");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_refuses_to_rewrite_diverged_trunk() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "remote-work"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Remote work"]),
+            TestCommand::git_ok(&["push", "origin", "remote-work:main"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Local-only main commit"]),
+            TestCommand::git_ok(&["tag", "local-main"]),
+
+            TestCommand::dagit_fail(&["update"])
+                .with_output_containing("dagit won't rewrite the trunk")
+                .with_output_containing("(git: fatal:"),
+            // main still has its local commit and wasn't rebased onto origin
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "local-main"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "local-main", "main"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "remote-work", "main"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update should fail rather than rewrite a diverged trunk");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_note_command() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::dagit_ok(&["note", "main", "refactor auth"]),
+            TestCommand::dagit_ok(&["dag"]),
+            TestCommand::dagit_ok(&["note", "main", "--clear"]),
+            TestCommand::dagit_fail(&["note", "main"]),
+            TestCommand::dagit_fail(&["note", "untracked", "text"]),
+        ]);
+
+    run_flow_test(test).expect("note should set and clear notes on tracked branches");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_doctor_command_on_healthy_dag() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["doctor"]),
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["doctor"]),
+        ]);
+
+    run_flow_test(test).expect("doctor should pass on a DAG built by dagit");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_doctor_flags_branches_at_the_same_commit() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["doctor"]),
+
+            TestCommand::git_ok(&["branch", "feature-copy", "feature"]),
+            TestCommand::dagit_ok(&["track", "feature-copy"]),
+            TestCommand::dagit_fail(&["doctor"])
+                .with_output_containing("'feature', 'feature-copy' point at the same commit"),
+        ]);
+
+    run_flow_test(test).expect("doctor should flag tracked branches that point at the same commit");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_doctor_flags_parent_that_is_no_longer_an_ancestor() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            // Rewriting main's history behind dagit's back
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--amend", "--allow-empty", "-m", "Rewritten initial commit"]),
+            TestCommand::dagit_fail(&["doctor"])
+                .with_output_containing("'main' is recorded as a parent of 'feature' but is not its git ancestor"),
+        ]);
+
+    run_flow_test(test).expect("doctor should flag edges git disagrees with");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_keeps_branches_with_own_commits() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("child".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+    expected_dag.add_parent_child_relationship("child", "feature").unwrap();
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::dagit_ok(&["update"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("Branches with their own commits are not redundant");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_keep_redundant() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature commit"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            // main catches up with feature, so feature has nothing of its own left
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["merge", "--ff-only", "feature"]),
+            TestCommand::dagit_ok(&["update", "--keep-redundant"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("--keep-redundant should leave redundant branches tracked");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_redundant_branch_children_move_to_its_parent() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    let feature_id = expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("child".to_string());
+    expected_dag.remove_branch(&feature_id);
+    expected_dag.add_parent_child_relationship("child", "main").unwrap();
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature commit"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child commit"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+
+            // feature lands on main, leaving it redundant
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["merge", "--ff-only", "feature"]),
+            TestCommand::dagit_ok(&["update"]),
+            TestCommand::dagit_ok(&["doctor"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("Children of a removed redundant branch should be relinked to its parent");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_preview_pr_leaves_dag_untouched() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["preview-pr"]),
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["preview-pr"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("preview-pr should work without gh and not record any PRs");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_resume_skips_completed_branches() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("clean".to_string());
+    let feature_id = expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("clean", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+    expected_dag.get_branch_mut(&feature_id).unwrap().last_failed_rebase = Some(FailedRebase::new("main", ""));
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "clean"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Clean work"]),
+            TestCommand::dagit_ok(&["track", "clean"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "FEATURE.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on feature"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "MAIN.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on main"]),
+
+            // clean is updated, feature conflicts
+            TestCommand::dagit_fail(&["update"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "clean"]),
+
+            // Move main again: resuming leaves the already updated clean alone
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "More main work"]),
+            TestCommand::dagit_fail(&["update", "--resume"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "clean"]),
+
+            // A fresh update processes every branch again
+            TestCommand::dagit_fail(&["update"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "clean"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("update --resume should skip branches the failed run updated");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_reconstruct_rebuilds_dag_from_git() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("child".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("main".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+    expected_dag.add_parent_child_relationship("child", "feature").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+
+            // Nothing is tracked yet, so there is no DAG to take the branch names from
+            TestCommand::dagit_fail(&["reconstruct"])
+                .with_output_containing("use --all"),
+            // Without a terminal to confirm on, nothing is written
+            TestCommand::dagit_ok(&["reconstruct", "--all"])
+                .with_output_containing("'child' on top of 'feature'")
+                .with_output_containing("Not writing the rebuilt DAG"),
+            TestCommand::dagit_ok(&["reconstruct", "--all", "--yes"])
+                .with_output_containing("Wrote the rebuilt DAG"),
+            // Rebuilding from the tracked branches gives the same DAG again
+            TestCommand::dagit_ok(&["reconstruct", "--yes"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("reconstruct should rebuild the DAG from git ancestry");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_dag_since() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["dag", "--since", "feature"]),
+            TestCommand::dagit_ok(&["dag", "--since", "feature", "--format", "dot"]),
+            TestCommand::dagit_fail(&["dag", "--since", "untracked"]),
+        ]);
+
+    run_flow_test(test).expect("dag --since should accept tracked branches only");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_dag_max_depth_and_roots_only() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::git_ok(&["checkout", "-b", "grandchild"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Grandchild work"]),
+            TestCommand::dagit_ok(&["track", "grandchild"]),
+
+            TestCommand::dagit_ok(&["dag", "--max-depth", "1"])
+                .with_output_containing("feature")
+                .with_output_containing("... (2 more)"),
+            TestCommand::dagit_ok(&["dag", "--roots-only", "--format", "compact"])
+                .with_output_containing("... (3 more)"),
+            TestCommand::dagit_fail(&["dag", "--roots-only", "--max-depth", "1"]),
+        ]);
+
+    run_flow_test(test).expect("dag --max-depth and --roots-only should summarize deeper branches");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_recursive_tracks_untracked_ancestors() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("base".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("child".to_string());
+    expected_dag.add_parent_child_relationship("base", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature", "base").unwrap();
+    expected_dag.add_parent_child_relationship("child", "feature").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "base"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Base work"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+
+            // Stops at main, which is already tracked
+            TestCommand::dagit_ok(&["track", "--recursive"])
+                .with_output_containing("Tracked 3 branches: base → feature → child"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("track --recursive should track the whole chain of untracked ancestors");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_worktrees_share_the_dag() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["worktree", "add", "-q", "-b", "feature", "linked"]),
+            TestCommand::git_ok(&["-C", "linked", "commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track"]).in_dir("linked"),
+            // The linked worktree saw main, and the main checkout sees feature
+            TestCommand::dagit_ok(&["dag"]).in_dir("linked").with_output_containing("main"),
+            TestCommand::dagit_ok(&["dag", "--parents-of", "feature"]).with_output_containing("main"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("Branches tracked in a linked worktree should show up in the main checkout's DAG");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_force_redetects_relationships() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("a".to_string());
+    expected_dag.create_branch("b".to_string());
+    expected_dag.add_parent_child_relationship("a", "main").unwrap();
+    expected_dag.add_parent_child_relationship("b", "a").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "a"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "A work"]),
+            TestCommand::dagit_ok(&["track", "a"]),
+            TestCommand::git_ok(&["checkout", "-b", "b", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "B work"]),
+            TestCommand::dagit_ok(&["track", "b"]),
+            // b moves onto a behind dagit's back, so its recorded parent main is stale
+            TestCommand::git_ok(&["rebase", "a"]),
+            TestCommand::dagit_ok(&["track", "b"])
+                .with_output_containing("already being tracked"),
+            TestCommand::dagit_ok(&["dag", "--parents-of", "b"]).with_output_containing("main"),
+            TestCommand::dagit_ok(&["track", "b", "--force"])
+                .with_output_containing("Detected parent: a"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("track --force should re-detect the relationships of a tracked branch");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_unrelated_branch_warns_about_new_root() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("docs".to_string());
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "--orphan", "docs"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Docs"]),
+            TestCommand::dagit_ok(&["track", "docs"])
+                .with_output_containing("no parent detected; 'docs' will be a new root")
+                .with_output_containing("dagit move docs --onto <PARENT>"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("Tracking a branch with no parent into a non-empty DAG should warn");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_max_distance_skips_far_parents() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("far".to_string());
+    expected_dag.create_branch("near".to_string());
+    expected_dag.add_parent_child_relationship("near", "main").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "far"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "First"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Second"]),
+            TestCommand::dagit_ok(&["track", "far", "--max-distance", "1"])
+                .with_output_containing("no parent detected; 'far' will be a new root"),
+            TestCommand::git_ok(&["checkout", "-b", "near", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Near work"]),
+            TestCommand::dagit_ok(&["track", "near", "--max-distance", "1"])
+                .with_output_containing("Detected parent: main (1 commits away)"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("track --max-distance should ignore parents that are too far away");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_install_and_uninstall_hooks() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["uninstall-hooks"])
+                .with_output_containing("is not installed"),
+            TestCommand::dagit_ok(&["install-hooks"])
+                .with_output_containing("Installed the dagit post-checkout hook"),
+            TestCommand::dagit_ok(&["install-hooks"])
+                .with_output_containing("already installed"),
+            // The hook must not get in the way of checkouts
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::dagit_ok(&["uninstall-hooks"])
+                .with_output_containing("Removed the dagit post-checkout hook"),
+            TestCommand::dagit_ok(&["uninstall-hooks"])
+                .with_output_containing("is not installed"),
+        ]);
+
+    run_flow_test(test).expect("install-hooks and uninstall-hooks should manage the post-checkout hook");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_next_and_prev_walk_a_linear_stack() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+
+            TestCommand::dagit_ok(&["prev"]).with_output_containing("'main' has no tracked parent"),
+            TestCommand::dagit_ok(&["next"]).with_output_containing("Now on 'feature'"),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "HEAD"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "HEAD", "main"]),
+            TestCommand::dagit_ok(&["next"]).with_output_containing("'feature' has no tracked children"),
+            TestCommand::dagit_ok(&["prev"]).with_output_containing("Now on 'main'"),
+
+            TestCommand::git_ok(&["checkout", "-b", "other"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Other work"]),
+            TestCommand::dagit_ok(&["track", "other"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::dagit_fail(&["next"]).with_output_containing("several children"),
+        ]);
+
+    run_flow_test(test).expect("next and prev should move along the stack");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_log_shows_branch_commits_by_author() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["-c", "user.name=Alice", "commit", "--allow-empty", "-m", "Alice's change"]),
+            TestCommand::git_ok(&["-c", "user.name=Bob", "commit", "--allow-empty", "-m", "Bob's change"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            TestCommand::dagit_ok(&["log"])
+                .with_output_containing("Alice's change")
+                .with_output_containing("Bob's change"),
+            TestCommand::dagit_ok(&["log", "feature", "--author", "Bob"])
+                .with_output_containing("Bob's change"),
+            // A root branch has no parent..branch range to filter
+            TestCommand::dagit_fail(&["log", "main", "--author", "Bob"])
+                .with_output_containing("no tracked parent"),
+        ]);
+
+    run_flow_test(test).expect("log should show the branch's commits, filtered by author");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_dag_abbrev() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::dagit_ok(&["dag", "--abbrev", "12"]),
+            TestCommand::dagit_ok(&["dag", "--abbrev", "0"]),
+            TestCommand::dagit_fail(&["dag", "--abbrev", "41"])
+                .with_output_containing("--abbrev must be at most 40"),
+        ]);
+
+    run_flow_test(test).expect("dag --abbrev should accept lengths up to the full hash");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_set_trunk_pins_the_fast_forwarded_branch() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["checkout", "-b", "develop"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Develop work"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "develop"]),
+            TestCommand::dagit_ok(&["track", "develop"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            TestCommand::dagit_fail(&["set-trunk", "untracked"])
+                .with_output_containing("Branch 'untracked' is not tracked"),
+            TestCommand::dagit_ok(&["set-trunk", "develop"])
+                .with_output_containing("'develop' is now the trunk"),
+            TestCommand::dagit_ok(&["dag"]).with_output_containing("develop (trunk)"),
+            // main is just another root once develop is the trunk
+            TestCommand::dagit_ok(&["update"])
+                .with_output_containing("Pulling trunk from origin (fast-forward only)")
+                .with_output_containing("Warning: branch 'main' is a root and will be rebased against origin/main"),
+            TestCommand::dagit_ok(&["submit", "--flat", "--dry-run"])
+                .with_output_containing("Flat mode: all PRs will target 'develop'"),
+
+            TestCommand::dagit_ok(&["set-trunk", "--clear"])
+                .with_output_containing("The trunk is no longer pinned"),
+        ]);
+
+    run_flow_test_with_origin(test).expect("set-trunk should pin the trunk used by update and flat submit");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_warns_about_roots_other_than_trunk() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            // Unrelated history, so release has no tracked ancestor and becomes a root
+            TestCommand::git_ok(&["checkout", "--orphan", "release"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Release work"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "release"]),
+            TestCommand::dagit_ok(&["track", "release"]),
+
+            TestCommand::dagit_ok(&["update"])
+                .with_output_containing("Warning: branch 'release' is a root and will be rebased against origin/release — is this intended?"),
+            TestCommand::dagit_ok(&["update", "--no-root-warnings"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update should warn about roots that aren't the trunk");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_current_stack_leaves_other_stacks_alone() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "other"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Other work"]),
+            TestCommand::dagit_ok(&["track", "other"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Main moved on"]),
+            TestCommand::git_ok(&["checkout", "feature"]),
+
+            TestCommand::dagit_ok(&["update", "--current-stack"])
+                .with_output_containing("Processing 2 branches"),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "feature"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "other"]),
+
+            TestCommand::git_ok(&["checkout", "-b", "untracked"]),
+            TestCommand::dagit_fail(&["update", "--current-stack"])
+                .with_output_containing("'untracked' is not tracked"),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update --current-stack should only update the current branch's stack");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_plan_describes_update_without_changing_anything() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["plan"])
+                .with_output_containing("1. main: trunk, up to date with origin/main")
+                .with_output_containing("2. feature: already on top of 'main'"),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Main moved on"]),
+            TestCommand::dagit_ok(&["plan"])
+                .with_output_containing("2. feature: rebase onto 'main'"),
+            // Nothing was rebased
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "feature"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("plan should describe the update without performing it");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_verify() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Main moved on"]),
+
+            TestCommand::dagit_ok(&["update", "--verify"])
+                .with_output_containing("Verified that every updated branch contains its primary parent"),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update --verify should pass after a clean update");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_return_to() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+
+            // feature has a commit that main lacks, so HEAD being an ancestor of main means main is checked out
+            TestCommand::dagit_ok(&["update"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "HEAD", "main"]),
+            TestCommand::dagit_ok(&["update", "--return-to", "feature"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "HEAD", "main"]),
+            TestCommand::dagit_fail(&["update", "--return-to", "missing"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update should check out the --return-to branch at the end");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_with_explicit_children() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("fix".to_string());
+    expected_dag.create_branch("base".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+    expected_dag.add_parent_child_relationship("fix", "main").unwrap();
+    expected_dag.add_parent_child_relationship("base", "main").unwrap();
+    expected_dag.add_parent_child_relationship("fix", "base").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "fix", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Fix work"]),
+            TestCommand::dagit_ok(&["track", "fix"]),
+
+            // Only the named child is wired, no children are detected
+            TestCommand::git_ok(&["checkout", "-b", "base", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Base work"]),
+            TestCommand::dagit_fail(&["track", "base", "--child", "untracked"]),
+            TestCommand::dagit_ok(&["track", "base", "--child", "fix"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("track --child should wire only the named children");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_track_between() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.create_branch("middle".to_string());
+    expected_dag.add_parent_child_relationship("middle", "main").unwrap();
+    expected_dag.add_parent_child_relationship("feature", "middle").unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "middle", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Middle work"]),
+            TestCommand::dagit_fail(&["track", "middle", "--between", "feature", "main"]),
+            TestCommand::dagit_ok(&["track", "middle", "--between", "main", "feature"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("track --between should splice the branch into the edge");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_submit_pushes_branches_before_opening_prs() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+
+            TestCommand::dagit_fail(&["submit", "--no-push"])
+                .with_output_containing("Branch 'feature' is not pushed to origin; run without --no-push or push it manually"),
+            TestCommand::git_fail(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/feature"]),
+
+            // The pushes happen even though gh then can't create the PRs in the test environment
+            TestCommand::dagit_fail(&["submit"]),
+            TestCommand::git_ok(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/feature"]),
+            TestCommand::git_ok(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/child"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("submit should push heads and bases before opening PRs");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_submit_concurrency_pushes_siblings() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "first"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "First work"]),
+            TestCommand::dagit_ok(&["track", "first"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "second"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Second work"]),
+            TestCommand::dagit_ok(&["track", "second"]),
+
+            TestCommand::dagit_fail(&["submit", "--concurrency", "0"])
+                .with_output_containing("--concurrency must be at least 1"),
+
+            // gh can't create the PRs in the test environment, but both siblings get pushed
+            TestCommand::dagit_fail(&["submit", "--concurrency", "2"])
+                .with_output_containing("*** Processing branch 'first' ***")
+                .with_output_containing("*** Processing branch 'second' ***"),
+            TestCommand::git_ok(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/first"]),
+            TestCommand::git_ok(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/second"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("submit --concurrency should handle sibling branches together");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_submit_requires_pr_base_on_origin() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::git_ok(&["push", "origin", "child"]),
+
+            // child itself is on origin, but the feature base it would be opened against isn't
+            TestCommand::dagit_ok(&["submit", "--no-push", "--dry-run"])
+                .with_output_containing("PR base 'feature' is not on origin; submit 'feature' first"),
+            TestCommand::dagit_fail(&["submit", "--no-push"])
+                .with_output_containing("PR base 'feature' is not on origin; submit 'feature' first"),
+        ]);
+
+    run_flow_test_with_origin(test).expect("submit should refuse PR bases that origin doesn't have");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_set_remote_name_pushes_and_opens_prs_under_remote_name() {
+    let test = FlowTestWithOrigin::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["config", "receive.denyCurrentBranch", "ignore"]),
+        ])
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+
+            TestCommand::dagit_fail(&["set-remote-name", "missing", "alice/missing"])
+                .with_output_containing("Branch 'missing' is not tracked"),
+            TestCommand::dagit_ok(&["set-remote-name", "feature", "alice/feature"])
+                .with_output_containing("'feature' is pushed to origin as 'alice/feature'"),
+
+            // The remote name is both the head of feature's PR and the base of child's
+            TestCommand::dagit_ok(&["submit", "--dry-run"])
+                .with_output_containing("refs/heads/feature:alice/feature")
+                .with_output_containing("--head alice/feature")
+                .with_output_containing("--base alice/feature"),
+
+            TestCommand::dagit_fail(&["submit"]),
+            TestCommand::git_ok(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/alice/feature"]),
+            TestCommand::git_fail(&["rev-parse", "--verify", "--quiet", "refs/remotes/origin/feature"]),
+
+            TestCommand::dagit_ok(&["set-remote-name", "feature", "--clear"])
+                .with_output_containing("'feature' is pushed to origin as 'feature'"),
+        ]);
+
+    run_flow_test_with_origin(test).expect("a branch's remote name should be used for pushes and PRs");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_keep_empty_preserves_emptied_branches() {
+    let mut expected_dag = Dag::new();
+    expected_dag.create_branch("main".to_string());
+    expected_dag.create_branch("feature".to_string());
+    expected_dag.add_parent_child_relationship("feature", "main").unwrap();
+
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["mv", "README.md", "DOCS.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on feature"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+
+            // main makes the same change, so rebasing empties feature's only commit
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "DOCS.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on main"]),
+
+            TestCommand::dagit_ok(&["update", "--keep-empty"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "feature", "main"]),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test_with_origin(test).expect("update --keep-empty should keep emptied commits and the branch");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_path_command() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            // Works before anything is tracked, when the file doesn't exist yet
+            TestCommand::dagit_ok(&["path"]).with_output_containing(".dagit/dag.json"),
+        ]);
+
+    run_flow_test(test).expect("path should print the DAG file location");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_prune_untracks_deleted_branches() {
+    // feature is deleted in git, so child moves up under main and keeps its ID
+    let mut expected_dag = Dag::new();
+    expected_dag.insert_branch(Branch::new(BranchId(1), "main")).unwrap();
+    expected_dag.insert_branch(Branch::new(BranchId(3), "child")).unwrap();
+    expected_dag.add_parent_child_relationship_by_id(BranchId(3), BranchId(1)).unwrap();
+
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::git_ok(&["branch", "-D", "feature"]),
+            TestCommand::dagit_ok(&["prune"]).with_output_containing("Pruned 'feature'"),
+            TestCommand::dagit_ok(&["prune"]).with_output_containing("All tracked branches still exist in git"),
+        ])
+        .with_expected_dag(expected_dag);
+
+    run_flow_test(test).expect("prune should untrack deleted branches and relink their children");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_exclude_skips_branch_and_descendants() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::git_ok(&["checkout", "-b", "other", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Other work"]),
+            TestCommand::dagit_ok(&["track", "other"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "More main work"]),
+
+            TestCommand::dagit_fail(&["update", "--exclude", "missing"])
+                .with_output_containing("Branch 'missing' is not tracked"),
+            TestCommand::dagit_ok(&["update", "--exclude", "feature"])
+                .with_output_containing("Skipping 'child' (excluded)"),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "other"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "feature"]),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "child"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update --exclude should leave the excluded subtree alone");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_interactive_rebase() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "More main work"]),
+
+            // git runs the sequence editor itself, so it takes the todo list as is here
+            TestCommand::git_ok(&["config", "sequence.editor", "true"]),
+            TestCommand::dagit_fail(&["update", "--interactive-rebase", "main"])
+                .with_output_containing("Branch 'main' has no parent to rebase onto"),
+            TestCommand::dagit_fail(&["update", "--interactive-rebase", "feature", "--keep-empty"])
+                .with_output_containing("cannot be used with"),
+            TestCommand::dagit_fail(&["update", "--interactive-rebase", "feature", "--allow-pr-rebase"])
+                .with_output_containing("cannot be used with"),
+            TestCommand::dagit_ok(&["update", "--interactive-rebase", "feature"])
+                .with_output_containing("Rebased 'feature' onto 'main'"),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "feature"]),
+        ]);
+
+    run_flow_test(test).expect("update --interactive-rebase should rebase the branch onto its parent");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_summary_command() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Feature work"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "child"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Child work"]),
+            TestCommand::dagit_ok(&["track", "child"]),
+            TestCommand::git_ok(&["checkout", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "More feature work"]),
+            TestCommand::dagit_ok(&["summary"])
+                .with_output_containing("Tracked branches: 3")
+                .with_output_containing("Recorded PRs:     0")
+                .with_output_containing("Open PRs:         0")
+                .with_output_containing("Out of date:      1")
+                .with_output_containing("Max stack depth:  2"),
+        ]);
+
+    run_flow_test(test).expect("summary should aggregate over the tracked branches");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_diff_against_parent() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["mv", "README.md", "FEATURE.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            // Changes on main since feature forked off are not part of feature's diff
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "More main work"]),
+
+            TestCommand::dagit_ok(&["diff", "feature"]).with_output_containing("FEATURE.md"),
+            TestCommand::dagit_fail(&["diff"]).with_output_containing("Branch 'main' has no tracked parent to diff against"),
+            TestCommand::dagit_fail(&["diff", "missing"]).with_output_containing("Branch 'missing' is not tracked"),
+        ]);
+
+    run_flow_test(test).expect("diff should show the branch's changes against its parent");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_update_fail_fast_stops_at_first_failure() {
+    let test = FlowTestWithOrigin::new()
+        .with_clone_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["mv", "README.md", "FEATURE.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on feature"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::git_ok(&["checkout", "-b", "other", "main"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Other work"]),
+            TestCommand::dagit_ok(&["track", "other"]),
+            TestCommand::git_ok(&["checkout", "main"]),
+            TestCommand::git_ok(&["mv", "README.md", "MAIN.md"]),
+            TestCommand::git_ok(&["commit", "-m", "Rename README on main"]),
+
+            // feature comes before its sibling other, so other is never reached
+            TestCommand::dagit_fail(&["update", "--fail-fast"])
+                .with_output_containing("Stopping after the first failure (--fail-fast)"),
+            TestCommand::git_fail(&["merge-base", "--is-ancestor", "main", "other"]),
+            TestCommand::dagit_fail(&["failed"]).with_output_containing("feature (failed to rebase onto 'main@"),
+
+            // By default the sibling subtree is still updated
+            TestCommand::dagit_fail(&["update"]),
+            TestCommand::git_ok(&["merge-base", "--is-ancestor", "main", "other"]),
+        ]);
+
+    run_flow_test_with_origin(test).expect("update --fail-fast should stop at the first failed branch");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_submit_and_preview_target_primary_parent_of_multi_parent_branch() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "first"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "First work"]),
+            TestCommand::dagit_ok(&["track", "first"]),
+            TestCommand::git_ok(&["checkout", "-b", "merged"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Merged work"]),
+            TestCommand::dagit_ok(&["track", "merged"]),
+            // second becomes merged's other parent
+            TestCommand::git_ok(&["checkout", "-b", "second", "main"]),
+            TestCommand::dagit_ok(&["track", "second", "--child", "merged"]),
+
+            TestCommand::dagit_ok(&["preview-pr"])
+                .with_output_containing("Base:  first"),
+            TestCommand::dagit_ok(&["submit", "--dry-run"])
+                .with_output_containing("--base first --head merged"),
+            TestCommand::dagit_ok(&["set-primary", "merged", "second"]),
+            TestCommand::dagit_ok(&["submit", "--dry-run"])
+                .with_output_containing("--base second --head merged"),
+        ]);
+
+    run_flow_test(test).expect("PRs of a branch with several parents should target its primary parent");
+}
+
+#[test]
+#[serial_test::serial]
+fn test_submit_dry_run_shows_commit_count_in_body() {
+    let test = FlowTest::new()
+        .with_commands(vec![
+            TestCommand::dagit_ok(&["track", "main"]),
+            TestCommand::git_ok(&["checkout", "-b", "feature"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "First"]),
+            TestCommand::git_ok(&["commit", "--allow-empty", "-m", "Second"]),
+            TestCommand::dagit_ok(&["track", "feature"]),
+            TestCommand::dagit_ok(&["submit", "--dry-run"])
+                .with_output_containing("--body '2 commits ahead of base'"),
+        ]);
+
+    run_flow_test(test).expect("submit should describe how many commits the PR contains");
+}
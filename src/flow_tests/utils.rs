@@ -13,10 +13,13 @@ pub enum TestCommand {
         args: Vec<String>, 
         should_succeed: bool 
     },
-    /// Dagit command with arguments and expected success/failure
+    /// Dagit command with arguments, expected success/failure and text its output must contain
+    /// Runs in the repository unless given a directory (relative to it) to run in
     Dagit { 
         args: Vec<String>, 
-        should_succeed: bool 
+        should_succeed: bool,
+        expected_output: Vec<String>,
+        dir: Option<String>,
     },
 }
 
@@ -42,6 +45,8 @@ impl TestCommand {
         TestCommand::Dagit {
             args: args.iter().map(|s| s.to_string()).collect(),
             should_succeed: true,
+            expected_output: Vec::new(),
+            dir: None,
         }
     }
     
@@ -50,7 +55,28 @@ impl TestCommand {
         TestCommand::Dagit {
             args: args.iter().map(|s| s.to_string()).collect(),
             should_succeed: false,
+            expected_output: Vec::new(),
+            dir: None,
+        }
+    }
+
+    /// Require the output (stdout or stderr) of a dagit command to contain the given text
+    pub fn with_output_containing(mut self, text: &str) -> Self {
+        match &mut self {
+            TestCommand::Dagit { expected_output, .. } => expected_output.push(text.to_string()),
+            TestCommand::Git { .. } => panic!("Output checks are only supported for dagit commands"),
+        }
+        self
+    }
+
+    /// Run a dagit command from another directory, e.g. a linked worktree
+    /// Git commands can use `git -C <dir>` instead
+    pub fn in_dir(mut self, path: &str) -> Self {
+        match &mut self {
+            TestCommand::Dagit { dir, .. } => *dir = Some(path.to_string()),
+            TestCommand::Git { .. } => panic!("Use git -C to run git commands in another directory"),
         }
+        self
     }
 }
 
@@ -83,6 +109,7 @@ impl FlowTest {
         self
     }
     
+    #[allow(dead_code)]
     pub fn add_command(mut self, command: TestCommand) -> Self {
         self.commands.push(command);
         self
@@ -132,7 +159,7 @@ pub fn run_flow_test(test: FlowTest) -> Result<(), String> {
             // Build the binary first
             env::set_current_dir(&original_dir).map_err(|e| format!("Failed to return to original dir: {}", e))?;
             let build_output = Command::new("cargo")
-                .args(&["build", "--bin", "dagit"])
+                .args(["build", "--bin", "dagit"])
                 .output()
                 .map_err(|e| format!("Failed to build dagit: {}", e))?;
 
@@ -150,14 +177,12 @@ pub fn run_flow_test(test: FlowTest) -> Result<(), String> {
                 TestCommand::Git { args, should_succeed } => {
                     execute_git_command(args, *should_succeed, i)
                 }
-                TestCommand::Dagit { args, should_succeed } => {
-                    execute_dagit_command(&dagit_path, args, *should_succeed, i)
+                TestCommand::Dagit { args, should_succeed, expected_output, dir } => {
+                    execute_dagit_command(&dagit_path, args, *should_succeed, expected_output, dir.as_deref(), i)
                 }
             };
 
-            if let Err(e) = result {
-                return Err(e);
-            }
+            result?;
         }
 
         // Check expected DAG if provided
@@ -224,7 +249,7 @@ pub fn run_flow_test_with_origin(test: FlowTestWithOrigin) -> Result<(), String>
             // Build the binary first
             env::set_current_dir(&original_dir).map_err(|e| format!("Failed to return to original dir: {}", e))?;
             let build_output = Command::new("cargo")
-                .args(&["build", "--bin", "dagit"])
+                .args(["build", "--bin", "dagit"])
                 .output()
                 .map_err(|e| format!("Failed to build dagit: {}", e))?;
 
@@ -242,8 +267,8 @@ pub fn run_flow_test_with_origin(test: FlowTestWithOrigin) -> Result<(), String>
                 TestCommand::Git { args, should_succeed } => {
                     execute_git_command(args, *should_succeed, i)
                 }
-                TestCommand::Dagit { args, should_succeed } => {
-                    execute_dagit_command(&dagit_path, args, *should_succeed, i)
+                TestCommand::Dagit { args, should_succeed, expected_output, dir } => {
+                    execute_dagit_command(&dagit_path, args, *should_succeed, expected_output, dir.as_deref(), i)
                 }
             };
 
@@ -297,7 +322,7 @@ fn setup_git_repo() -> Result<(), String> {
     
     // Create initial commit only if no commits exist
     let status_output = Command::new("git")
-        .args(&["rev-parse", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .current_dir(env::current_dir().unwrap())
         .output()
         .map_err(|e| format!("Failed to check git status: {}", e))?;
@@ -313,9 +338,10 @@ fn setup_git_repo() -> Result<(), String> {
 }
 
 /// Get the current git branch name in the current directory
+#[allow(dead_code)]
 fn get_current_branch_name() -> Result<String, String> {
     let output = Command::new("git")
-        .args(&["branch", "--show-current"])
+        .args(["branch", "--show-current"])
         .current_dir(env::current_dir().unwrap())
         .output()
         .map_err(|e| format!("Failed to get current branch: {}", e))?;
@@ -341,10 +367,11 @@ fn execute_git_command(args: &[String], should_succeed: bool, command_index: usi
     run_command("git", &args_str, should_succeed, &format!("git command {}", command_index))
 }
 
-fn execute_dagit_command(dagit_path: &Path, args: &[String], should_succeed: bool, command_index: usize) -> Result<(), String> {
+fn execute_dagit_command(dagit_path: &Path, args: &[String], should_succeed: bool, expected_output: &[String], dir: Option<&str>, command_index: usize) -> Result<(), String> {
+    let current_dir = env::current_dir().unwrap();
     let output = Command::new(dagit_path)
         .args(args)
-        .current_dir(env::current_dir().unwrap())
+        .current_dir(dir.map(|dir| current_dir.join(dir)).unwrap_or(current_dir))
         .output()
         .map_err(|e| format!("Failed to execute dagit command {}: {}", command_index, e))?;
     
@@ -364,6 +391,15 @@ fn execute_dagit_command(dagit_path: &Path, args: &[String], should_succeed: boo
         ));
     }
 
+    for text in expected_output {
+        if !stdout.contains(text.as_str()) && !stderr.contains(text.as_str()) {
+            return Err(format!(
+                "Dagit command {} output doesn't contain '{}'\nCommand: dagit {}\nStdout: {}\nStderr: {}",
+                command_index, text, args.join(" "), stdout, stderr
+            ));
+        }
+    }
+
     Ok(())
 }
 
@@ -455,6 +491,25 @@ fn verify_dag_state(expected_dag: Dag) -> Result<(), String> {
                         ));
                     }
                 }
+
+                // Check PR number and failed rebase only when the expected DAG sets them
+                if expected_branch.pr_number.is_some() && actual_branch.pr_number != expected_branch.pr_number {
+                    return Err(format!(
+                        "Branch {} ('{}') PR number mismatch: expected {:?}, got {:?}",
+                        expected_id.0, expected_branch.git_name,
+                        expected_branch.pr_number, actual_branch.pr_number
+                    ));
+                }
+
+                // The target commit depends on the hashes of the run, so only the target is compared
+                let failed_target = |branch: &crate::dag::Branch| branch.last_failed_rebase.as_ref().map(|failed| failed.target.clone());
+                if expected_branch.last_failed_rebase.is_some() && failed_target(actual_branch) != failed_target(expected_branch) {
+                    return Err(format!(
+                        "Branch {} ('{}') last failed rebase mismatch: expected {:?}, got {:?}",
+                        expected_id.0, expected_branch.git_name,
+                        expected_branch.last_failed_rebase, actual_branch.last_failed_rebase
+                    ));
+                }
             }
             None => {
                 return Err(format!(
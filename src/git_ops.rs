@@ -0,0 +1,190 @@
+//! The git and gh operations the CLI needs, behind a trait so they can be swapped out
+//!
+//! `RealGit` forwards every call to the free functions in [`crate::git`], which shell out to
+//! `git` and `gh`. Tests can implement `GitOps` with canned results instead, to exercise
+//! command logic without spawning any processes.
+
+use std::path::PathBuf;
+use crate::dag::{Branch, BranchId, Dag};
+use crate::git::{self, GitError, InteractiveRebase, OriginRebase, PrMetadata, PrState, RebaseOriginError};
+
+/// The operations on the repository and its pull requests that dagit's commands perform
+/// Each method mirrors the free function of the same name in [`crate::git`]
+/// Implementations must be usable from several threads, since submit creates PRs in parallel
+pub trait GitOps: Sync {
+    fn get_current_git_branch(&self) -> Result<String, String>;
+    fn is_current_branch(&self, branch_name: &str) -> Result<bool, String>;
+    fn is_git_repository(&self) -> Result<bool, String>;
+    fn has_commits(&self) -> bool;
+    fn branch_exists(&self, branch_name: &str) -> Result<bool, String>;
+    fn origin_branch_exists(&self, branch_name: &str) -> Result<bool, String>;
+    fn create_branch_from_origin(&self, branch_name: &str) -> Result<(), String>;
+    fn get_all_branches(&self) -> Result<Vec<String>, String>;
+    fn get_branch_commit(&self, branch: &str) -> Result<String, GitError>;
+    fn count_commits_between_cached(&self, from: &str, to: &str) -> Result<u32, String>;
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, GitError>;
+    fn find_closest_parent(&self, target_branch: &str, candidate_branches: &[String], max_distance: Option<u32>) -> Result<Option<(String, u32)>, String>;
+    fn find_closest_children(&self, target_branch: &str, candidate_branches: &[String]) -> Result<Vec<String>, String>;
+    fn rebase_branch(&self, branch: &mut Branch, target_branch: &str, keep_empty: bool) -> Result<(), GitError>;
+    fn rebase_branch_updating_refs(&self, branch: &mut Branch, target_branch: &str, keep_empty: bool) -> Result<(), GitError>;
+    fn checkout_branch(&self, branch_name: &str) -> Result<(), GitError>;
+    fn reset_branch(&self, branch_name: &str, commit: &str) -> Result<(), GitError>;
+    fn interactive_rebase_branch(&self, branch: &mut Branch, target_branch: &str) -> Result<InteractiveRebase, GitError>;
+    fn show_diff_since(&self, base: &str, branch: &str) -> Result<(), GitError>;
+    fn show_log_since(&self, base: &str, branch: &str, author: Option<&str>) -> Result<(), GitError>;
+    fn fetch_from_origin(&self) -> Result<(), GitError>;
+    fn rebase_against_origin(&self, branch: &mut Branch, keep_empty: bool) -> Result<OriginRebase, RebaseOriginError>;
+    fn git_supports_update_refs(&self) -> bool;
+    fn get_default_branch(&self, remote: &str) -> Result<String, String>;
+    fn get_trunk_branch(&self) -> Option<String>;
+    fn pull_rebase_ff(&self, branch_name: &str, remote: &str, remote_branch: &str) -> Result<(), String>;
+    fn get_remote_url(&self) -> Result<String, String>;
+    fn get_pr_title_template(&self) -> Option<String>;
+    fn pr_body(&self, head: &str, base: &str) -> String;
+    fn create_pr_for_branch(&self, branch_id: BranchId, dag: &mut Dag, base_override: Option<&str>, metadata: &PrMetadata, push: bool) -> Result<Option<usize>, String>;
+    fn update_pr_target_for_branch(&self, branch_id: BranchId, dag: &Dag, new_target_branch: &str) -> Result<(), String>;
+    fn get_pr_state(&self, pr_number: usize) -> Result<PrState, String>;
+    fn close_pr(&self, pr_number: usize) -> Result<(), String>;
+    fn get_hooks_dir(&self) -> Result<PathBuf, String>;
+}
+
+/// Runs the real `git` and `gh` binaries in the current directory
+pub struct RealGit;
+
+impl GitOps for RealGit {
+    fn get_current_git_branch(&self) -> Result<String, String> {
+        git::get_current_git_branch()
+    }
+
+    fn is_current_branch(&self, branch_name: &str) -> Result<bool, String> {
+        git::is_current_branch(branch_name)
+    }
+
+    fn is_git_repository(&self) -> Result<bool, String> {
+        git::is_git_repository()
+    }
+
+    fn has_commits(&self) -> bool {
+        git::has_commits()
+    }
+
+    fn branch_exists(&self, branch_name: &str) -> Result<bool, String> {
+        git::branch_exists(branch_name)
+    }
+
+    fn origin_branch_exists(&self, branch_name: &str) -> Result<bool, String> {
+        git::origin_branch_exists(branch_name)
+    }
+
+    fn create_branch_from_origin(&self, branch_name: &str) -> Result<(), String> {
+        git::create_branch_from_origin(branch_name)
+    }
+
+    fn get_all_branches(&self) -> Result<Vec<String>, String> {
+        git::get_all_branches()
+    }
+
+    fn get_branch_commit(&self, branch: &str) -> Result<String, GitError> {
+        git::get_branch_commit(branch)
+    }
+
+    fn count_commits_between_cached(&self, from: &str, to: &str) -> Result<u32, String> {
+        git::count_commits_between_cached(from, to)
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, GitError> {
+        git::is_ancestor(ancestor, descendant)
+    }
+
+    fn find_closest_parent(&self, target_branch: &str, candidate_branches: &[String], max_distance: Option<u32>) -> Result<Option<(String, u32)>, String> {
+        git::find_closest_parent(target_branch, candidate_branches, max_distance)
+    }
+
+    fn find_closest_children(&self, target_branch: &str, candidate_branches: &[String]) -> Result<Vec<String>, String> {
+        git::find_closest_children(target_branch, candidate_branches)
+    }
+
+    fn rebase_branch(&self, branch: &mut Branch, target_branch: &str, keep_empty: bool) -> Result<(), GitError> {
+        git::rebase_branch(branch, target_branch, keep_empty)
+    }
+
+    fn rebase_branch_updating_refs(&self, branch: &mut Branch, target_branch: &str, keep_empty: bool) -> Result<(), GitError> {
+        git::rebase_branch_updating_refs(branch, target_branch, keep_empty)
+    }
+
+    fn checkout_branch(&self, branch_name: &str) -> Result<(), GitError> {
+        git::checkout_branch(branch_name)
+    }
+
+    fn reset_branch(&self, branch_name: &str, commit: &str) -> Result<(), GitError> {
+        git::reset_branch(branch_name, commit)
+    }
+
+    fn interactive_rebase_branch(&self, branch: &mut Branch, target_branch: &str) -> Result<InteractiveRebase, GitError> {
+        git::interactive_rebase_branch(branch, target_branch)
+    }
+
+    fn show_diff_since(&self, base: &str, branch: &str) -> Result<(), GitError> {
+        git::show_diff_since(base, branch)
+    }
+
+    fn show_log_since(&self, base: &str, branch: &str, author: Option<&str>) -> Result<(), GitError> {
+        git::show_log_since(base, branch, author)
+    }
+
+    fn fetch_from_origin(&self) -> Result<(), GitError> {
+        git::fetch_from_origin()
+    }
+
+    fn rebase_against_origin(&self, branch: &mut Branch, keep_empty: bool) -> Result<OriginRebase, RebaseOriginError> {
+        git::rebase_against_origin(branch, keep_empty)
+    }
+
+    fn git_supports_update_refs(&self) -> bool {
+        git::git_supports_update_refs()
+    }
+
+    fn get_default_branch(&self, remote: &str) -> Result<String, String> {
+        git::get_default_branch(remote)
+    }
+
+    fn get_trunk_branch(&self) -> Option<String> {
+        git::get_trunk_branch()
+    }
+
+    fn pull_rebase_ff(&self, branch_name: &str, remote: &str, remote_branch: &str) -> Result<(), String> {
+        git::pull_rebase_ff(branch_name, remote, remote_branch)
+    }
+
+    fn get_remote_url(&self) -> Result<String, String> {
+        git::get_remote_url()
+    }
+
+    fn get_pr_title_template(&self) -> Option<String> {
+        git::get_pr_title_template()
+    }
+
+    fn pr_body(&self, head: &str, base: &str) -> String {
+        git::pr_body(head, base)
+    }
+
+    fn create_pr_for_branch(&self, branch_id: BranchId, dag: &mut Dag, base_override: Option<&str>, metadata: &PrMetadata, push: bool) -> Result<Option<usize>, String> {
+        git::create_pr_for_branch(branch_id, dag, base_override, metadata, push)
+    }
+
+    fn update_pr_target_for_branch(&self, branch_id: BranchId, dag: &Dag, new_target_branch: &str) -> Result<(), String> {
+        git::update_pr_target_for_branch(branch_id, dag, new_target_branch)
+    }
+
+    fn get_pr_state(&self, pr_number: usize) -> Result<PrState, String> {
+        git::get_pr_state(pr_number)
+    }
+
+    fn close_pr(&self, pr_number: usize) -> Result<(), String> {
+        git::close_pr(pr_number)
+    }
+
+    fn get_hooks_dir(&self) -> Result<PathBuf, String> {
+        git::get_hooks_dir()
+    }
+}
@@ -0,0 +1,9 @@
+//! Track git branches as a DAG of stacked changes and keep them rebased on each other
+//!
+//! The `dagit` binary is a thin CLI over these modules; they can also be used directly to
+//! build, inspect or persist a DAG from other tools.
+
+pub mod dag;
+pub mod git;
+pub mod git_ops;
+pub mod serde;
@@ -1,26 +1,106 @@
-mod dag;
-mod serde;
-mod git;
+use dagit::{dag, git, git_ops, serde};
+use dag::BranchStatus;
 
 #[cfg(test)]
 mod flow_tests;
+#[cfg(test)]
+mod mock_git;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use git::{get_current_git_branch, find_closest_parent, find_closest_children, fetch_from_origin, rebase_against_origin, rebase_branch, RebaseOriginError, create_pr_for_branch, get_branch_commit, is_ancestor, is_current_branch};
-use serde::{read_dag_from_file, write_dag_to_file};
-use std::collections::HashSet;
+use git::{OriginRebase, RebaseOriginError, resolve_pr_base, pr_base_missing_message, build_pr_create_args, local_branch_ref, parse_remote_url, pr_title, PrMetadata, PrState, InteractiveRebase};
+use git_ops::{GitOps, RealGit};
+use serde::{read_dag_from_file, write_dag_to_file, restore_dag_from_backup, read_update_state, write_update_state, clear_update_state, UpdateState, get_dag_file_path};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, IsTerminal, Write};
+
+/// Compute the sync status of a branch
+/// A failed update wins over everything else; otherwise the branch is up to date when all of
+/// its tracked parents are ancestors of it
+fn branch_status(git: &dyn GitOps, branch: &dag::Branch, dag: &dag::Dag) -> BranchStatus {
+    if branch.last_failed_rebase.is_some() {
+        return BranchStatus::FailedUpdate;
+    }
+    if branch.parents.is_empty() {
+        return BranchStatus::NoParent;
+    }
+
+    for parent_id in &branch.parents {
+        if let Some(parent_branch) = dag.get_branch(parent_id) {
+            match git.is_ancestor(&local_branch_ref(&parent_branch.git_name), &local_branch_ref(&branch.git_name)) {
+                Ok(true) => {}
+                Ok(false) => return BranchStatus::OutOfDate,
+                Err(_) => return BranchStatus::Unknown,
+            }
+        }
+    }
+
+    BranchStatus::UpToDate
+}
+
+/// Longest branch name shown in the compact dag view before it is cut off
+const COMPACT_NAME_WIDTH: usize = 24;
+
+/// Commit hash characters shown in the dag view unless --abbrev says otherwise
+const DEFAULT_HASH_ABBREV: usize = 7;
+
+/// Cut a name down to `width` characters, marking the cut with an ellipsis
+fn truncate_name(name: &str, width: usize) -> String {
+    if name.chars().count() <= width {
+        return name.to_string();
+    }
+    let mut truncated: String = name.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// One line of the compact dag view: marker, truncated name and a status glyph
+fn get_compact_branch_info(git: &dyn GitOps, branch: &dag::Branch, dag: &dag::Dag, show_ids: bool) -> String {
+    let marker = if git.is_current_branch(&branch.git_name).unwrap_or_default() {
+        ">".green().bold().to_string()
+    } else {
+        "*".to_string()
+    };
+    let id_info = if show_ids {
+        format!("[{}] ", branch.uid.0).dimmed().to_string()
+    } else {
+        "".to_string()
+    };
+
+    format!("{}{} {} {}", id_info, marker, highlight_root(branch, dag, truncate_name(&branch.git_name, COMPACT_NAME_WIDTH)), branch_status(git, branch, dag).glyph())
+}
 
-fn get_branch_info(branch: &dag::Branch, indent: usize, dag: &dag::Dag) -> Result<String, String> {
+/// Make the names of root branches stand out, since a mistaken root gets rebased against origin,
+/// and mark the trunk if one is pinned
+fn highlight_root(branch: &dag::Branch, dag: &dag::Dag, name: String) -> String {
+    let name = if branch.parents.is_empty() {
+        name.cyan().bold().to_string()
+    } else {
+        name
+    };
+    if dag.trunk == Some(branch.uid) {
+        format!("{} {}", name, "(trunk)".magenta())
+    } else {
+        name
+    }
+}
+
+/// `abbrev` is how many characters of the commit hash to show, with 0 meaning all of them
+fn get_branch_info(git: &dyn GitOps, branch: &dag::Branch, indent: usize, dag: &dag::Dag, show_ids: bool, abbrev: usize) -> Result<String, String> {
     // Get indent spaces
     let indent_str = " ".repeat(indent);
 
-    // Determine marker: "*" if not current, colored ">" if current
-    let is_current = match is_current_branch(&branch.git_name) {
-        Ok(is_current) => is_current,
-        Err(_) => false, // Default to non-current if we can't determine
+    // Internal id, to correlate the tree with dag.json and with errors that mention ids
+    let id_info = if show_ids {
+        format!("[{}] ", branch.uid.0).dimmed().to_string()
+    } else {
+        "".to_string()
     };
 
+    // Determine marker: "*" if not current, colored ">" if current
+    // Default to non-current if we can't determine
+    let is_current = git.is_current_branch(&branch.git_name).unwrap_or_default();
+
     let marker = if is_current {
         ">".green().bold().to_string()
     } else {
@@ -28,11 +108,11 @@ fn get_branch_info(branch: &dag::Branch, indent: usize, dag: &dag::Dag) -> Resul
     };
 
     // Get commit hash
-    let commit_hash = match get_branch_commit(&branch.git_name) {
+    let commit_hash = match git.get_branch_commit(&local_branch_ref(&branch.git_name)) {
         Ok(hash) => {
-            // Take first 7 characters of hash for brevity
-            if hash.len() >= 7 {
-                hash[..7].yellow().to_string()
+            // Abbreviate the hash for brevity
+            if abbrev > 0 && hash.len() >= abbrev {
+                hash[..abbrev].yellow().to_string()
             } else {
                 hash.yellow().to_string()
             }
@@ -40,29 +120,19 @@ fn get_branch_info(branch: &dag::Branch, indent: usize, dag: &dag::Dag) -> Resul
         Err(_) => "unknown".yellow().to_string(),
     };
 
-    // Determine status
-    let status = if branch.last_failed_rebase.is_some() {
-        "❌ failed update"
-    } else {
-        // Check if all parents are ancestors
-        let mut all_parents_are_ancestors = true;
-        for parent_id in &branch.parents {
-            if let Some(parent_branch) = dag.get_branch(parent_id) {
-                // We need to check if the parent is an ancestor of this branch
-                if !is_ancestor(&parent_branch.git_name, &branch.git_name).unwrap() {
-                    all_parents_are_ancestors = false;
-                    break;
-                }
-            }
-        }
+    let status = branch_status(git, branch, dag);
 
-        if all_parents_are_ancestors && !branch.parents.is_empty() {
-            "✅ up to date"
-        } else {
-            "🔄 out of date"
-        }
+    // What a failed update failed against, e.g. " onto main@abc1234"
+    let failure_info = match &branch.last_failed_rebase {
+        Some(failed) => format!(" onto {}", describe_failed_rebase(failed, abbrev)),
+        None => "".to_string(),
     };
 
+    // How far the branch is ahead of / behind its primary parent
+    let ahead_behind = ahead_behind_info(git, branch, dag)
+        .map(|info| format!(" {}", info))
+        .unwrap_or_default();
+
     // PR number if exists
     let pr_info = if let Some(pr_num) = branch.pr_number {
         format!("PR #{}", pr_num).yellow().to_string()
@@ -70,121 +140,819 @@ fn get_branch_info(branch: &dag::Branch, indent: usize, dag: &dag::Dag) -> Resul
         "".to_string()
     };
 
+    // Note if exists, dimmed so it doesn't compete with the status
+    let note_info = match &branch.note {
+        Some(note) => format!(" {}", note.dimmed()),
+        None => "".to_string(),
+    };
+
     // Build and return the formatted string
-    Ok(format!("{}{} {}|{}|{}|{}",
+    Ok(format!("{}{}{} {}|{}|{}|{}{}",
                indent_str,
+               id_info,
                marker,
                commit_hash,
-               branch.git_name,
-               status,
-               pr_info.trim()))
+               highlight_root(branch, dag, branch.git_name.clone()),
+               status.to_string() + &failure_info + &ahead_behind,
+               pr_info.trim(),
+               note_info))
+}
+
+/// Render a failed rebase as "<target>@<commit>", with the commit abbreviated like in the dag
+/// view (0 keeps it whole); just the target when the commit isn't known
+fn describe_failed_rebase(failed: &dag::FailedRebase, abbrev: usize) -> String {
+    if failed.target_commit.is_empty() {
+        return failed.target.clone();
+    }
+    let commit = match failed.target_commit.get(..abbrev) {
+        Some(short) if abbrev > 0 => short,
+        _ => &failed.target_commit,
+    };
+    format!("{}@{}", failed.target, commit)
+}
+
+/// Print why a rebase in update failed, including the exact commit it was rebased onto so the
+/// user can reproduce it by hand
+fn print_rebase_failure(error: &git::GitError, branch: &dag::Branch) {
+    println!("✗ Failed: {}", error);
+    if let Some(failed) = branch.last_failed_rebase.as_ref().filter(|failed| !failed.target_commit.is_empty()) {
+        println!("    '{}' was at {} (reproduce with: git rebase {})", failed.target, failed.target_commit, failed.target_commit);
+    }
+}
+
+/// Render how many commits a branch is ahead of and behind its primary parent, e.g. "↑3 ↓1"
+/// Returns None for root branches or when git can't compare the two
+fn ahead_behind_info(git: &dyn GitOps, branch: &dag::Branch, dag: &dag::Dag) -> Option<String> {
+    let parent_branch = branch.parents.first().and_then(|parent_id| dag.get_branch(parent_id))?;
+    let parent_ref = local_branch_ref(&parent_branch.git_name);
+    let branch_ref = local_branch_ref(&branch.git_name);
+
+    let ahead = git.count_commits_between_cached(&parent_ref, &branch_ref).ok()?;
+    let behind = git.count_commits_between_cached(&branch_ref, &parent_ref).ok()?;
+    Some(format_ahead_behind(ahead, behind))
+}
+
+fn format_ahead_behind(ahead: u32, behind: u32) -> String {
+    format!("↑{} ↓{}", ahead, behind)
 }
 
 #[derive(Parser)]
 #[command(name = "dagit")]
 #[command(about = "A DAG-based git branch management tool", long_about = None)]
 struct Cli {
+    /// Output format for errors
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human)]
+    output: OutputFormat,
+    /// When to use colors in the output
+    #[arg(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+    /// Never use colors (same as --color never)
+    #[arg(long, global = true)]
+    no_color: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+enum ColorMode {
+    /// Use colors when stdout is a terminal and NO_COLOR is not set
+    Auto,
+    /// Always use colors, even when piping
+    Always,
+    /// Never use colors
+    Never,
+}
+
+/// Decide whether to colorize output
+/// Explicit flags win over the NO_COLOR environment variable, which wins over TTY detection
+fn should_colorize(mode: ColorMode, no_color_flag: bool, no_color_env: bool, stdout_is_tty: bool) -> bool {
+    if no_color_flag {
+        return false;
+    }
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => !no_color_env && stdout_is_tty,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable messages on stderr
+    Human,
+    /// A JSON object on stdout, for scripting
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DagFormat {
+    /// An ASCII tree for the terminal
+    Tree,
+    /// The tree with just the name and a status glyph per branch, for narrow terminals
+    Compact,
+    /// A Graphviz digraph, e.g. for `dot -Tpng`
+    Dot,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Track a git branch in the DAG
     Track {
         /// Name of the branch to track (defaults to current branch)
         branch_name: Option<String>,
+        /// Ask before adding each detected parent/child relationship
+        #[arg(long)]
+        confirm: bool,
+        /// Make this tracked branch a child instead of detecting children (repeatable)
+        #[arg(long = "child", value_name = "BRANCH")]
+        children: Vec<String>,
+        /// Splice the branch into the edge between these two tracked branches
+        #[arg(long, num_args = 2, value_names = ["PARENT", "CHILD"], conflicts_with = "children")]
+        between: Option<Vec<String>>,
+        /// Only consider parents at most this many commits behind the branch (faster in long histories)
+        #[arg(long, value_name = "N")]
+        max_distance: Option<u32>,
+        /// Also track the branch's untracked ancestors, down to a tracked branch or the trunk
+        #[arg(long, conflicts_with_all = ["children", "between"])]
+        recursive: bool,
+        /// If the branch is already tracked, drop its relationships and detect them again
+        #[arg(long, conflicts_with_all = ["between", "recursive"])]
+        force: bool,
+        /// Create the local branch from origin/<BRANCH> if it only exists on origin
+        #[arg(long)]
+        from_origin: bool,
     },
     /// Update all tracked branches by rebasing against origin and parents
-    Update,
+    Update {
+        /// Rebase root branches (those without a tracked parent) onto this ref
+        #[arg(long, value_name = "REF")]
+        onto: Option<String>,
+        /// Rebase each linear stack with a single `git rebase --update-refs` (needs git 2.38+)
+        #[arg(long)]
+        update_refs: bool,
+        /// Report branches that have no commits beyond their parent instead of untracking them
+        #[arg(long)]
+        keep_redundant: bool,
+        /// Skip the branches that the previous, partially failed update already updated
+        #[arg(long)]
+        resume: bool,
+        /// Also rebase branches whose PR is open (force-pushing them can lose review threads)
+        #[arg(long)]
+        allow_pr_rebase: bool,
+        /// Branch to check out when done (defaults to the branch checked out before the update)
+        #[arg(long, value_name = "BRANCH")]
+        return_to: Option<String>,
+        /// Keep empty commits when rebasing, including ones the rebase itself empties
+        #[arg(long)]
+        keep_empty: bool,
+        /// Leave this branch and its descendants untouched (repeatable)
+        #[arg(long, value_name = "BRANCH")]
+        exclude: Vec<String>,
+        /// Stop at the first branch that fails to update instead of continuing with the others
+        #[arg(long)]
+        fail_fast: bool,
+        /// Close the open PRs of branches removed for having no commits beyond their parent
+        #[arg(long)]
+        close_merged_prs: bool,
+        /// Don't warn about root branches other than the trunk being rebased against origin
+        #[arg(long)]
+        no_root_warnings: bool,
+        /// Only run `git rebase -i` of this branch onto its primary parent, in your editor
+        #[arg(long, value_name = "BRANCH", conflicts_with_all = [
+            "onto", "update_refs", "keep_redundant", "resume", "allow_pr_rebase", "return_to", "keep_empty",
+            "exclude", "fail_fast", "close_merged_prs", "no_root_warnings", "current_stack", "verify",
+        ])]
+        interactive_rebase: Option<String>,
+        /// Only update the current branch's stack: its ancestors and descendants
+        #[arg(long)]
+        current_stack: bool,
+        /// Afterwards, check that every updated branch contains its primary parent
+        #[arg(long)]
+        verify: bool,
+    },
     /// Submit PRs for all tracked branches
-    Submit,
+    Submit {
+        /// Open every PR against the default branch instead of the branch's parent
+        #[arg(long)]
+        flat: bool,
+        /// Print the gh commands that would be run without executing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Request a review from this user or team on every new PR (repeatable)
+        #[arg(long = "reviewer", value_name = "LOGIN")]
+        reviewers: Vec<String>,
+        /// Add this label to every new PR (repeatable)
+        #[arg(long = "label", value_name = "NAME")]
+        labels: Vec<String>,
+        /// Don't push branches that aren't on origin yet, failing their PRs instead
+        #[arg(long)]
+        no_push: bool,
+        /// Title for new PRs, with {branch} and {base} placeholders [default: config dagit.prTitleTemplate, else "{branch} -> {base}"]
+        #[arg(long, value_name = "TEMPLATE")]
+        title_template: Option<String>,
+        /// Create up to this many PRs at once, for branches that don't depend on each other
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        concurrency: usize,
+    },
     /// Print the DAG structure
-    Dag,
+    Dag {
+        /// How to render the DAG
+        #[arg(long, value_enum, default_value_t = DagFormat::Tree)]
+        format: DagFormat,
+        /// Only print the names of the branch's direct parents, one per line
+        #[arg(long, value_name = "BRANCH", conflicts_with = "children_of")]
+        parents_of: Option<String>,
+        /// Only print the names of the branch's direct children, one per line
+        #[arg(long, value_name = "BRANCH")]
+        children_of: Option<String>,
+        /// Only show this branch and its descendants
+        #[arg(long, value_name = "BRANCH")]
+        since: Option<String>,
+        /// Prefix each branch with its internal id
+        #[arg(long)]
+        show_ids: bool,
+        /// Only print branches up to this many levels below the roots, summarizing the rest
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+        /// Only print the root branches, summarizing everything below them
+        #[arg(long, conflicts_with = "max_depth")]
+        roots_only: bool,
+        /// How many characters of each commit hash to show; 0 shows the full hash
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_HASH_ABBREV)]
+        abbrev: usize,
+    },
+    /// Restore the DAG file from the backup taken before the last write
+    Restore,
+    /// Make a parent the primary parent of a branch (used as rebase target and PR base)
+    SetPrimary {
+        /// Name of the child branch
+        child: String,
+        /// Name of the parent branch to make primary
+        parent: String,
+    },
+    /// Move a branch and its descendants onto a new parent, rebasing them to match
+    Move {
+        /// Name of the branch to move
+        branch: String,
+        /// Name of the tracked branch that becomes its only parent
+        #[arg(long, value_name = "PARENT")]
+        onto: String,
+    },
+    /// Fold a duplicate branch into another: its children move to the destination and it is untracked
+    MergeNodes {
+        /// Name of the branch to remove from the DAG
+        src: String,
+        /// Name of the branch that takes over its children
+        dst: String,
+    },
+    /// Show where the current branch sits in the DAG
+    Current,
+    /// Check out the tracked child of the current branch
+    Next,
+    /// Check out the primary parent of the current branch
+    Prev,
+    /// Attach a free-text note to a branch, shown in the dag view
+    Note {
+        /// Name of the branch
+        branch: String,
+        /// The note text
+        #[arg(required_unless_present = "clear")]
+        text: Option<String>,
+        /// Remove the branch's note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
+    /// Set the name a branch is pushed under and has on origin, e.g. `alice/feature`
+    SetRemoteName {
+        /// Name of the local branch
+        branch: String,
+        /// Name of the branch on origin
+        #[arg(required_unless_present = "clear")]
+        remote_name: Option<String>,
+        /// Go back to pushing the branch under its local name
+        #[arg(long, conflicts_with = "remote_name")]
+        clear: bool,
+    },
+    /// Pin the trunk: the branch update fast-forwards and flat submit targets
+    SetTrunk {
+        /// Name of the tracked branch to use as the trunk
+        #[arg(required_unless_present = "clear")]
+        branch: Option<String>,
+        /// Go back to the configured trunk (dagit.trunk) or the default branch
+        #[arg(long, conflicts_with = "branch")]
+        clear: bool,
+    },
+    /// Check the DAG file for inconsistencies, exiting non-zero if any are found
+    Doctor,
+    /// Show the PRs submit would open (base, head and title) without running gh
+    PreviewPr,
+    /// List the branches whose last rebase failed, exiting non-zero if there are any
+    Failed,
+    /// Print the absolute path of the DAG file, whether or not it exists yet
+    Path,
+    /// Untrack branches that no longer exist in git, relinking their children to their parents
+    Prune,
+    /// Rebuild the DAG from git ancestry alone, e.g. after dag.json was lost or damaged
+    Reconstruct {
+        /// Rebuild from every local branch instead of the branches the DAG tracks
+        #[arg(long)]
+        all: bool,
+        /// Write the rebuilt DAG without asking first
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Install a post-checkout hook that reminds you to track new branches
+    InstallHooks,
+    /// Remove the hook installed by install-hooks, keeping the rest of the post-checkout hook
+    UninstallHooks,
+    /// Print a JSON Schema of the DAG file (.dagit/dag.json), for validating it in other tools
+    Schema,
+    /// Print counts of tracked branches, PRs and branches needing attention
+    Summary,
+    /// Print what update would do to each branch, without fetching or changing anything
+    Plan,
+    /// Show the changes a branch made on top of its (primary) parent
+    Diff {
+        /// Name of the branch (defaults to current branch)
+        branch: Option<String>,
+    },
+    /// Show the commits a branch has on top of its primary parent
+    Log {
+        /// Name of the branch (defaults to current branch)
+        branch: Option<String>,
+        /// Only show commits whose author matches this pattern (passed to git log --author)
+        #[arg(long, value_name = "PATTERN")]
+        author: Option<String>,
+    },
+}
+
+impl Commands {
+    /// The subcommand name as typed on the command line
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Track { .. } => "track",
+            Commands::Update { .. } => "update",
+            Commands::Submit { .. } => "submit",
+            Commands::Dag { .. } => "dag",
+            Commands::Restore => "restore",
+            Commands::SetPrimary { .. } => "set-primary",
+            Commands::Move { .. } => "move",
+            Commands::MergeNodes { .. } => "merge-nodes",
+            Commands::Current => "current",
+            Commands::Next => "next",
+            Commands::Prev => "prev",
+            Commands::Note { .. } => "note",
+            Commands::SetRemoteName { .. } => "set-remote-name",
+            Commands::SetTrunk { .. } => "set-trunk",
+            Commands::Doctor => "doctor",
+            Commands::PreviewPr => "preview-pr",
+            Commands::Failed => "failed",
+            Commands::Path => "path",
+            Commands::Prune => "prune",
+            Commands::Reconstruct { .. } => "reconstruct",
+            Commands::InstallHooks => "install-hooks",
+            Commands::UninstallHooks => "uninstall-hooks",
+            Commands::Schema => "schema",
+            Commands::Summary => "summary",
+            Commands::Plan => "plan",
+            Commands::Diff { .. } => "diff",
+            Commands::Log { .. } => "log",
+        }
+    }
+}
+
+/// An error that aborts a command
+/// Branch-level failures are kept separately so they can be reported in structured output
+#[derive(Debug)]
+struct CommandError {
+    message: String,
+    failed_branches: Vec<String>,
+    skipped_branches: Vec<String>,
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError {
+            message,
+            failed_branches: Vec::new(),
+            skipped_branches: Vec::new(),
+        }
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    match &cli.command {
-        Commands::Track { branch_name } => {
-            handle_track_command(branch_name.clone());
+    // NO_COLOR only counts when set to a non-empty value, see https://no-color.org
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    colored::control::set_override(should_colorize(cli.color, cli.no_color, no_color_env, io::stdout().is_terminal()));
+
+    let git: &dyn GitOps = &RealGit;
+
+    // Every command but schema works on the current repository's DAG; checking once up front gives
+    // one clear message instead of whichever git or DAG file error a command happens to hit first
+    if !matches!(cli.command, Commands::Schema) {
+        let in_repository = match git.is_git_repository() {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("dagit must be run inside a git repository".to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = in_repository {
+            report_error(cli.output, cli.command.name(), &e.into());
+            std::process::exit(1);
+        }
+    }
+
+    let result = match &cli.command {
+        Commands::Track { branch_name, confirm, children, between, max_distance, recursive, force, from_origin } => {
+            let options = TrackOptions {
+                confirm: *confirm,
+                children: children.clone(),
+                between: between.as_ref().map(|names| (names[0].clone(), names[1].clone())),
+                max_distance: *max_distance,
+                recursive: *recursive,
+                force: *force,
+                from_origin: *from_origin,
+            };
+            handle_track_command(git, branch_name.clone(), &options)
+        }
+        Commands::Update { onto, update_refs, keep_redundant, resume, allow_pr_rebase, return_to, keep_empty, exclude, fail_fast, close_merged_prs, no_root_warnings, interactive_rebase, current_stack, verify } => {
+            let options = UpdateOptions {
+                onto: onto.clone(),
+                update_refs: *update_refs,
+                keep_redundant: *keep_redundant,
+                resume: *resume,
+                allow_pr_rebase: *allow_pr_rebase,
+                return_to: return_to.clone(),
+                keep_empty: *keep_empty,
+                exclude: exclude.clone(),
+                fail_fast: *fail_fast,
+                close_merged_prs: *close_merged_prs,
+                root_warnings: !*no_root_warnings,
+                interactive_rebase: interactive_rebase.clone(),
+                current_stack: *current_stack,
+                verify: *verify,
+                trunk: git.get_trunk_branch(),
+            };
+            handle_update_command(git, &options)
+        }
+        Commands::Submit { flat, dry_run, reviewers, labels, no_push, title_template, concurrency } => {
+            let options = SubmitOptions {
+                flat: *flat,
+                dry_run: *dry_run,
+                push: !*no_push,
+                concurrency: *concurrency,
+                metadata: PrMetadata {
+                    reviewers: reviewers.clone(),
+                    labels: labels.clone(),
+                    title_template: title_template.clone().or_else(|| git.get_pr_title_template()),
+                },
+            };
+            handle_submit_command(git, &options)
+        }
+        Commands::Dag { format, parents_of, children_of, since, show_ids, max_depth, roots_only, abbrev } => {
+            let options = DagOptions {
+                format: *format,
+                parents_of: parents_of.clone(),
+                children_of: children_of.clone(),
+                since: since.clone(),
+                show_ids: *show_ids,
+                max_depth: if *roots_only { Some(0) } else { *max_depth },
+                abbrev: *abbrev,
+            };
+            handle_dag_command(git, &options)
+        }
+        Commands::Restore => {
+            handle_restore_command()
+        }
+        Commands::SetPrimary { child, parent } => {
+            handle_set_primary_command(child, parent)
+        }
+        Commands::Move { branch, onto } => {
+            handle_move_command(git, branch, onto)
+        }
+        Commands::MergeNodes { src, dst } => {
+            handle_merge_nodes_command(src, dst)
+        }
+        Commands::Current => {
+            handle_current_command(git)
+        }
+        Commands::Next => {
+            handle_step_command(git, StackStep::Next)
+        }
+        Commands::Prev => {
+            handle_step_command(git, StackStep::Prev)
         }
-        Commands::Update => {
-            handle_update_command();
+        Commands::Note { branch, text, clear } => {
+            handle_note_command(branch, if *clear { None } else { text.clone() })
         }
-        Commands::Submit => {
-            handle_submit_command();
+        Commands::SetRemoteName { branch, remote_name, clear } => {
+            handle_set_remote_name_command(branch, if *clear { None } else { remote_name.clone() })
         }
-        Commands::Dag => {
-            handle_dag_command();
+        Commands::SetTrunk { branch, clear } => {
+            handle_set_trunk_command(if *clear { None } else { branch.as_deref() })
         }
+        Commands::Doctor => {
+            handle_doctor_command(git)
+        }
+        Commands::PreviewPr => {
+            handle_preview_pr_command(git)
+        }
+        Commands::Failed => {
+            handle_failed_command()
+        }
+        Commands::Path => {
+            handle_path_command()
+        }
+        Commands::Prune => {
+            handle_prune_command(git)
+        }
+        Commands::Reconstruct { all, yes } => {
+            handle_reconstruct_command(git, *all, *yes)
+        }
+        Commands::InstallHooks => {
+            handle_install_hooks_command(git)
+        }
+        Commands::UninstallHooks => {
+            handle_uninstall_hooks_command(git)
+        }
+        Commands::Schema => {
+            handle_schema_command()
+        }
+        Commands::Summary => {
+            handle_summary_command(git)
+        }
+        Commands::Plan => {
+            handle_plan_command(git)
+        }
+        Commands::Diff { branch } => {
+            handle_diff_command(git, branch.clone())
+        }
+        Commands::Log { branch, author } => {
+            handle_log_command(git, branch.clone(), author.as_deref())
+        }
+    };
+
+    if let Err(e) = result {
+        report_error(cli.output, cli.command.name(), &e);
+        std::process::exit(1);
     }
 }
 
-fn handle_track_command(branch_name: Option<String>) {
+/// Report a command failure in the requested output format
+fn report_error(output: OutputFormat, command: &str, error: &CommandError) {
+    match output {
+        OutputFormat::Human => eprintln!("Error: {}", error.message),
+        OutputFormat::Json => println!("{}", format_error_json(command, error)),
+    }
+}
+
+fn format_error_json(command: &str, error: &CommandError) -> String {
+    serde_json::json!({
+        "error": error.message,
+        "command": command,
+        "failed_branches": error.failed_branches,
+        "skipped_branches": error.skipped_branches,
+    }).to_string()
+}
+
+/// Load the DAG from its file (an empty DAG if the file doesn't exist yet)
+fn load_dag() -> Result<dag::Dag, CommandError> {
+    read_dag_from_file().map_err(|e| format!("Failed to read DAG file: {}", e).into())
+}
+
+/// Save the DAG back to its file
+fn save_dag(dag: &dag::Dag) -> Result<(), CommandError> {
+    write_dag_to_file(dag).map_err(|e| format!("Failed to write DAG file: {}", e).into())
+}
+
+/// Find the id of a tracked branch by its git name
+fn find_tracked_branch(dag: &dag::Dag, git_name: &str) -> Result<dag::BranchId, CommandError> {
+    dag.find_branch_by_name(git_name)
+        .map(|branch| branch.uid)
+        .ok_or_else(|| format!("Branch '{}' is not tracked", git_name).into())
+}
+
+/// Resolve branch ids to their git names, sorted by name
+fn branch_names(dag: &dag::Dag, branch_ids: &HashSet<dag::BranchId>) -> Vec<String> {
+    let mut names: Vec<String> = branch_ids.iter()
+        .filter_map(|id| dag.get_branch(id))
+        .map(|branch| branch.git_name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Fail with a clear message in a repository without commits, instead of whichever git error
+/// about an unresolvable HEAD a command would hit first
+fn ensure_has_commits(git: &dyn GitOps) -> Result<(), CommandError> {
+    if git.has_commits() {
+        Ok(())
+    } else {
+        Err("repository has no commits yet".into())
+    }
+}
+
+/// Options controlling how `dagit track` wires up a new or (with force) already tracked branch
+#[derive(Default)]
+struct TrackOptions {
+    /// Prompt before adding each detected relationship
+    confirm: bool,
+    /// Explicit children, used instead of detecting them
+    children: Vec<String>,
+    /// Parent and child whose edge the branch is inserted into, instead of detecting relationships
+    between: Option<(String, String)>,
+    /// Farthest, in commits, a detected parent may be
+    max_distance: Option<u32>,
+    /// Track the untracked ancestors too, see untracked_ancestor_chain
+    recursive: bool,
+    /// Re-detect the relationships of a branch that is already tracked
+    force: bool,
+    /// Create a branch that only exists on origin locally, instead of failing
+    from_origin: bool,
+}
+
+/// Ask the user a yes/no question on stdin, defaulting to no
+fn confirm(prompt: &str) -> bool {
+    print!("{} [y/N] ", prompt);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn handle_track_command(git: &dyn GitOps, branch_name: Option<String>, options: &TrackOptions) -> Result<(), CommandError> {
+    ensure_has_commits(git)?;
+
     // Get the branch name to track
     let branch_to_track = match branch_name {
         Some(name) => name,
-        None => match get_current_git_branch() {
-            Ok(current_branch) => current_branch,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                std::process::exit(1);
-            }
-        }
+        None => git.get_current_git_branch()?,
     };
     
-    println!("Tracking branch: {}", branch_to_track);
-    
     // Load existing DAG from file (or create new one if file doesn't exist)
-    let mut dag = match read_dag_from_file() {
-        Ok(dag) => {
-            dag
+    let mut dag = load_dag()?;
+    
+    // Check if branch already exists, before touching the DAG or git
+    if let Some(branch) = dag.find_branch_by_name(&branch_to_track) {
+        if !options.force {
+            println!("Branch '{}' is already being tracked (use --force to detect its relationships again)", branch_to_track);
+            return Ok(());
         }
-        Err(e) => {
-            eprintln!("Failed to read DAG file: {}", e);
-            std::process::exit(1);
+        // Keeping the branch keeps its ID, PR number and notes, unlike untracking and tracking it
+        let branch_id = branch.uid;
+        dag.detach(branch_id)?;
+        println!("Re-detecting relationships of branch {}", branch_to_track);
+        detect_relationships(git, &mut dag, &branch_to_track, options)?;
+        return save_dag(&dag);
+    }
+
+    if options.recursive {
+        let chain = untracked_ancestor_chain(git, &dag, &branch_to_track, options.max_distance)?;
+        // Track from the oldest ancestor up, so each branch detects the one tracked before it
+        for name in chain.iter().rev() {
+            track_branch(git, &mut dag, name, options)?;
         }
-    };
-    
-    // Check if branch already exists
-    for (_, branch) in &dag.branches {
-        if branch.git_name == branch_to_track {
-            println!("Branch '{}' is already being tracked", branch_to_track);
-            return;
+        let chain: Vec<&str> = chain.iter().rev().map(String::as_str).collect();
+        println!("Tracked {} branches: {}", chain.len(), chain.join(" → "));
+        return save_dag(&dag);
+    }
+
+    track_branch(git, &mut dag, &branch_to_track, options)?;
+    save_dag(&dag)
+}
+
+/// The branch followed by its untracked ancestors, closest first, each found by detecting the
+/// closest parent among all local branches
+/// Stops before the first tracked ancestor, at the trunk, or when no parent is found
+fn untracked_ancestor_chain(git: &dyn GitOps, dag: &dag::Dag, branch_name: &str, max_distance: Option<u32>) -> Result<Vec<String>, CommandError> {
+    let trunk = dag.trunk_branch().map(|trunk| trunk.git_name.clone()).or_else(|| git.get_trunk_branch());
+    let local_branches = git.get_all_branches()?;
+
+    let mut chain = vec![branch_name.to_string()];
+    while trunk.as_deref() != chain.last().map(String::as_str) {
+        let current = chain.last().cloned().unwrap_or_default();
+        // Parents are strictly behind their children, so this can't loop
+        let Some((parent, _)) = git.find_closest_parent(&current, &local_branches, max_distance)? else {
+            break;
+        };
+        if dag.find_branch_by_name(&parent).is_some() {
+            break;
+        }
+        chain.push(parent);
+    }
+    Ok(chain)
+}
+
+/// Add a branch that isn't tracked yet to the DAG and wire up its relationships, as
+/// described by `options`
+fn track_branch(git: &dyn GitOps, dag: &mut dag::Dag, branch_to_track: &str, options: &TrackOptions) -> Result<(), CommandError> {
+    let branch_to_track = branch_to_track.to_string();
+
+    // Only real local branches can be tracked; names like `HEAD` or `origin/main`
+    // would otherwise be resolved to some other revision by git, and a typo'd name
+    // would add a phantom branch that breaks `update`
+    if !git.branch_exists(&branch_to_track)? {
+        // A branch that only exists on origin gets a local counterpart when asked to, like
+        // `git checkout` would do
+        if !git.origin_branch_exists(&branch_to_track).unwrap_or(false) {
+            return Err(format!("'{}' is not a local git branch", branch_to_track).into());
+        }
+        if !options.from_origin {
+            return Err(format!("Branch '{}' does not exist locally, only on origin (use --from-origin to create it)", branch_to_track).into());
         }
+        git.create_branch_from_origin(&branch_to_track)?;
+        println!("Created local branch '{}' from 'origin/{}'", branch_to_track, branch_to_track);
+    }
+
+    for child_name in &options.children {
+        find_tracked_branch(dag, child_name)?;
     }
+    let between = match &options.between {
+        Some((parent_name, child_name)) => Some((find_tracked_branch(dag, parent_name)?, find_tracked_branch(dag, child_name)?)),
+        None => None,
+    };
+
+    println!("Tracking branch: {}", branch_to_track);
     
     // Create new branch with unique ID
-    let _branch_id = dag.create_branch(branch_to_track.clone());
+    let branch_id = dag.create_branch(branch_to_track.clone());
     println!("Tracking branch {}", branch_to_track);
-    
-    // Auto-detect parent and child relationships
+
+    if let (Some((parent_id, child_id)), Some((parent_name, child_name))) = (between, &options.between) {
+        dag.insert_between(branch_id, parent_id, child_id)?;
+        println!("  → Inserted between '{}' and '{}'", parent_name, child_name);
+
+        // The child's PR should now be reviewed against the new branch
+        if dag.get_branch(&child_id).is_some_and(|child| child.pr_number.is_some()) {
+            match git.update_pr_target_for_branch(child_id, dag, &branch_to_track) {
+                Ok(()) => println!("  Updated PR target for '{}' to '{}'", child_name, branch_to_track),
+                Err(e) => println!("  Warning: Failed to update PR target for '{}': {}", child_name, e),
+            }
+        }
+        return Ok(());
+    }
+
+    detect_relationships(git, dag, &branch_to_track, options)
+}
+
+/// Detect the parent and children of a tracked branch that has no relationships yet, or use
+/// the explicit children in `options`
+fn detect_relationships(git: &dyn GitOps, dag: &mut dag::Dag, branch_to_track: &str, options: &TrackOptions) -> Result<(), CommandError> {
     let tracked_branches = dag.get_tracked_branch_names();
     
     // Find the closest parent
-    match find_closest_parent(&branch_to_track, &tracked_branches) {
-        Ok(Some(parent_name)) => {
-            match dag.add_parent_child_relationship(&branch_to_track, &parent_name) {
-                Ok(()) => println!("  → Detected parent: {}", parent_name),
-                Err(e) => eprintln!("Warning: Failed to add parent relationship: {}", e),
+    match git.find_closest_parent(branch_to_track, &tracked_branches, options.max_distance) {
+        Ok(Some((parent_name, distance))) => {
+            println!("  → Detected parent: {} ({} commits away)", parent_name, distance);
+            if options.confirm && !confirm(&format!("    Make '{}' the parent of '{}'?", parent_name, branch_to_track)) {
+                println!("    Skipped parent '{}'", parent_name);
+            } else if let Err(e) = dag.add_parent_child_relationship(branch_to_track, &parent_name) {
+                eprintln!("Warning: Failed to add parent relationship: {}", e);
             }
         }
+        // Every branch but the first being a root is more likely a detection miss than intended
+        Ok(None) if dag.len() > 1 => {
+            println!("  → No parent detected");
+            println!(
+                "{} no parent detected; '{}' will be a new root. If it belongs on another branch, run: dagit move {} --onto <PARENT>",
+                "Warning:".yellow().bold(), branch_to_track, branch_to_track,
+            );
+        }
         Ok(None) => println!("  → No parent detected"),
         Err(e) => eprintln!("Warning: Failed to detect parent: {}", e),
     }
     
+    // Explicit children replace detection; unlike detected ones they must all be added
+    if !options.children.is_empty() {
+        for child_name in &options.children {
+            println!("  → Child: {}", child_name);
+            dag.add_parent_child_relationship(child_name, branch_to_track)?;
+        }
+        return Ok(());
+    }
+
     // Find the closest children
-    match find_closest_children(&branch_to_track, &tracked_branches) {
+    match git.find_closest_children(branch_to_track, &tracked_branches) {
         Ok(children) => {
             if children.is_empty() {
                 println!("  → No children detected");
             } else {
                 for child_name in &children {
-                    match dag.add_parent_child_relationship(child_name, &branch_to_track) {
-                        Ok(()) => println!("  → Detected child: {}", child_name),
-                        Err(e) => eprintln!("Warning: Failed to add child relationship: {}", e),
+                    println!("  → Detected child: {}", child_name);
+                    if options.confirm && !confirm(&format!("    Make '{}' a child of '{}'?", child_name, branch_to_track)) {
+                        println!("    Skipped child '{}'", child_name);
+                    } else if let Err(e) = dag.add_parent_child_relationship(child_name, branch_to_track) {
+                        eprintln!("Warning: Failed to add child relationship: {}", e);
                     }
                 }
             }
@@ -192,24 +960,79 @@ fn handle_track_command(branch_name: Option<String>) {
         Err(e) => eprintln!("Warning: Failed to detect children: {}", e),
     }
     
-    // Save updated DAG back to file
-    match write_dag_to_file(&dag) {
-        Ok(()) => {}
-        Err(e) => {
-            eprintln!("Failed to write DAG file: {}", e);
-            std::process::exit(1);
-        }
-    }
+    Ok(())
+}
+
+/// The current branch with its recursive parents and children, i.e. the stack it is part of
+/// Siblings of its ancestors are other stacks, and aren't included
+fn current_stack(git: &dyn GitOps, dag: &dag::Dag) -> Result<HashSet<dag::BranchId>, CommandError> {
+    let current = git.get_current_git_branch()?;
+    let branch_id = dag.find_branch_by_name(&current)
+        .map(|branch| branch.uid)
+        .ok_or_else(|| format!("The current branch '{}' is not tracked, so it has no stack to update", current))?;
+
+    let mut stack = dag.get_recursive_parents(branch_id);
+    stack.extend(dag.get_recursive_children(branch_id));
+    Ok(stack)
+}
+
+/// Options controlling how `dagit update` rebases the tracked branches
+#[derive(Clone, Default)]
+struct UpdateOptions {
+    /// Ref that root branches are rebased onto, instead of having no parent step
+    onto: Option<String>,
+    /// Rebase linear stacks in one go with `git rebase --update-refs`
+    update_refs: bool,
+    /// Only update the current branch's stack, see current_stack
+    current_stack: bool,
+    /// Branch that is fast-forwarded to origin instead of rebased (see get_trunk_branch)
+    /// A trunk pinned in the DAG takes precedence, see update_all_branches
+    trunk: Option<String>,
+    /// Leave redundant branches (no commits beyond their parent) in the DAG
+    keep_redundant: bool,
+    /// Skip branches completed by the previous incomplete update (see UpdateState)
+    resume: bool,
+    /// Rebase branches with open PRs instead of leaving them alone
+    allow_pr_rebase: bool,
+    /// Branch to check out at the end, instead of the one that was checked out at the start
+    return_to: Option<String>,
+    /// Keep commits that are or become empty in every rebase (see rebase_branch)
+    keep_empty: bool,
+    /// Branches skipped along with all of their descendants
+    exclude: Vec<String>,
+    /// Leave the remaining branches alone once one branch fails
+    fail_fast: bool,
+    /// Close the open PR of a branch that is removed as redundant, instead of only warning
+    close_merged_prs: bool,
+    /// Warn about roots other than the trunk, see root_warnings
+    root_warnings: bool,
+    /// Instead of updating everything, interactively rebase just this branch
+    interactive_rebase: Option<String>,
+    /// Check afterwards that each updated branch contains its primary parent, see unverified_edges
+    verify: bool,
+}
+
+/// What update_branch did with the branches handed to it so far
+#[derive(Default)]
+struct UpdateProgress {
+    /// Branches whose rebase failed, so that their children are skipped
+    failed: HashSet<dag::BranchId>,
+    skipped: HashSet<dag::BranchId>,
+    /// Why each PR that couldn't be checked kept its branch from being updated
+    pr_check_errors: Vec<String>,
 }
 
 fn update_branch(
+    git: &dyn GitOps,
     dag: &mut dag::Dag,
     branch_id: dag::BranchId,
-    failed_branches: &mut HashSet<dag::BranchId>,
-    skipped_branches: &mut HashSet<dag::BranchId>,
+    step: (usize, usize),
+    options: &UpdateOptions,
+    progress: &mut UpdateProgress,
 ) {
+    let UpdateProgress { failed: failed_branches, skipped: skipped_branches, pr_check_errors } = progress;
     let branch_name = dag.get_branch(&branch_id).map(|b| b.git_name.clone()).unwrap_or_else(|| "unknown".to_string());
-    println!("*** Processing branch '{}' ***", branch_name);
+    println!("[{}/{}] *** Processing branch '{}' ***", step.0, step.1, branch_name);
 
     // Get branch info first to avoid borrowing conflicts
     let (branch_name, branch_parents, should_skip) = {
@@ -233,16 +1056,52 @@ fn update_branch(
         return;
     }
 
+    // Rebasing a branch with an open PR force-updates the PR, so leave those alone unless allowed
+    // A redundant branch is exempt, since update removes it and deals with its PR below
+    if let Some(pr_number) = dag.get_branch(&branch_id).and_then(|b| b.pr_number) {
+        if !options.allow_pr_rebase && !is_redundant(git, dag, branch_id) {
+            match git.get_pr_state(pr_number) {
+                Ok(PrState::Open) => {
+                    println!("  Skipping '{}', open PR #{} (use --allow-pr-rebase)", branch_name, pr_number);
+                    skipped_branches.insert(branch_id);
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    // Usually the same error for every PR (e.g. gh is missing), so it's reported once at the end
+                    println!("  Skipping '{}', couldn't check whether PR #{} is open (use --allow-pr-rebase)", branch_name, pr_number);
+                    pr_check_errors.push(e);
+                    skipped_branches.insert(branch_id);
+                    return;
+                }
+            }
+        }
+    }
+
     println!("  Processing branch: {}", branch_name);
 
     // Get mutable reference to the branch for rebasing
     let mut branch_failed = false;
 
-    // Step 1: Rebase against origin
-    if let Some(branch_mut) = dag.get_branch_mut(&branch_id) {
+    // Step 1: Rebase against origin, or fast-forward if this is the trunk
+    if options.trunk.as_deref() == Some(branch_name.as_str()) {
+        print!("    Pulling trunk from origin (fast-forward only)... ");
+        let remote_name = dag.get_branch(&branch_id).map(|b| b.remote_branch_name().to_string()).unwrap_or_else(|| branch_name.clone());
+        match git.origin_branch_exists(&remote_name) {
+            Ok(false) => println!("✗ Skipped: origin branch does not exist"),
+            _ => match git.pull_rebase_ff(&branch_name, "origin", &remote_name) {
+                Ok(()) => println!("✓ Success"),
+                Err(e) => {
+                    println!("✗ Failed: {}", e);
+                    branch_failed = true;
+                }
+            },
+        }
+    } else if let Some(branch_mut) = dag.get_branch_mut(&branch_id) {
         print!("    Rebasing against origin... ");
-        match rebase_against_origin(branch_mut) {
-            Ok(()) => println!("✓ Success"),
+        match git.rebase_against_origin(branch_mut, options.keep_empty) {
+            Ok(OriginRebase::Rebased) => println!("✓ Success"),
+            Ok(OriginRebase::AlreadyUpToDate) => println!("✓ Already up to date with origin"),
             Err(RebaseOriginError::OriginDoesntExist) => {
                 println!("✗ Skipped: origin branch does not exist");
             }
@@ -255,8 +1114,14 @@ fn update_branch(
 
     // Step 2: Rebase against first parent (if no failure so far and has parents)
     if !branch_failed && !branch_parents.is_empty() {
+        // Until branches can be rebased onto all their parents, only the primary one (the first,
+        // see set-primary) is used
         if branch_parents.len() > 1 {
-            todo!("Handle multiple parents - for now only supporting single parent");
+            let others: Vec<String> = branch_parents[1..].iter()
+                .filter_map(|parent_id| dag.get_branch(parent_id))
+                .map(|parent| format!("'{}'", parent.git_name))
+                .collect();
+            println!("    Warning: '{}' has several parents; only rebasing onto the primary one, not onto {}", branch_name, others.join(", "));
         }
 
         let first_parent_id = branch_parents[0];
@@ -272,16 +1137,31 @@ fn update_branch(
         if let Some(branch_mut) = dag.get_branch_mut(&branch_id) {
             print!("    Rebasing against parent '{}'... ", parent_name);
 
-            match rebase_branch(branch_mut, &parent_name) {
+            match git.rebase_branch(branch_mut, &parent_name, options.keep_empty) {
                 Ok(()) => println!("✓ Success"),
                 Err(e) => {
-                    println!("✗ Failed: {}", e);
+                    print_rebase_failure(&e, branch_mut);
                     branch_failed = true;
                 }
             }
         }
     } else if !branch_failed {
-        println!("    No parent to rebase against");
+        match &options.onto {
+            Some(onto) => {
+                if let Some(branch_mut) = dag.get_branch_mut(&branch_id) {
+                    print!("    Rebasing root onto '{}'... ", onto);
+
+                    match git.rebase_branch(branch_mut, onto, options.keep_empty) {
+                        Ok(()) => println!("✓ Success"),
+                        Err(e) => {
+                            print_rebase_failure(&e, branch_mut);
+                            branch_failed = true;
+                        }
+                    }
+                }
+            }
+            None => println!("    No parent to rebase against"),
+        }
     }
 
     // If any rebase failed, mark this branch as failed
@@ -297,20 +1177,29 @@ fn update_branch(
             if let Some(parent_branch) = dag.get_branch(&parent_id) {
                 let parent_name = parent_branch.git_name.clone();
 
-                // Check if parent is an ancestor of this branch
-                println!("    Checking if '{}' is ancestor of '{}'...", parent_name, branch_name);
-                let is_ancestor = match git::is_ancestor(&parent_name, &branch_name) {
+                // The branch is redundant if it is an ancestor of its parent, i.e. it has no
+                // commits of its own left on top of the parent
+                println!("    Checking if '{}' is ancestor of '{}'...", branch_name, parent_name);
+                let is_ancestor = match git.is_ancestor(&local_branch_ref(&branch_name), &local_branch_ref(&parent_name)) {
                     Ok(result) => result,
                     Err(e) => {
                         println!("    Error checking ancestry: {} - skipping redundant check", e);
                         false
                     }
                 };
+                if is_ancestor && options.keep_redundant {
+                    println!("    Branch '{}' is redundant with parent '{}' - keeping it (--keep-redundant)", branch_name, parent_name);
+                    return;
+                }
                 if is_ancestor {
                     println!("    *** REMOVING BRANCH '{}' ***", branch_name);
-                    println!("    Yes! '{}' is ancestor of '{}'", parent_name, branch_name);
+                    println!("    Yes! '{}' is ancestor of '{}'", branch_name, parent_name);
                     println!("    Branch '{}' is behind parent '{}' - removing from DAG", branch_name, parent_name);
 
+                    if let Some(pr_number) = dag.get_branch(&branch_id).and_then(|b| b.pr_number) {
+                        close_redundant_pr(git, &branch_name, pr_number, options.close_merged_prs);
+                    }
+
                     // Get all children of this branch before removing it
                     let children = dag.get_branch(&branch_id)
                         .map(|b| b.children.clone())
@@ -335,10 +1224,10 @@ fn update_branch(
                             child_mut.parents.retain(|&p| p != branch_id);
 
                             // Add the new parent-child relationship
-                            dag.add_parent_child_relationship_by_id(parent_id, child_id).unwrap();
+                            dag.add_parent_child_relationship_by_id(child_id, parent_id).unwrap();
 
                             // Update the PR target to point to the new parent
-                            if let Err(e) = git::update_pr_target_for_branch(child_id, &dag, &parent_name) {
+                            if let Err(e) = git.update_pr_target_for_branch(child_id, dag, &parent_name) {
                                 println!("      Warning: Failed to update PR target for '{}': {}", child_name, e);
                             } else {
                                 println!("      Updated PR target for '{}' to '{}'", child_name, parent_name);
@@ -358,179 +1247,1387 @@ fn update_branch(
     }
 }
 
-fn handle_update_command() {
-    println!("Starting update process...");
-    
-    // Load existing DAG from file
-    let mut dag = match read_dag_from_file() {
-        Ok(dag) => dag,
+/// Roots other than the trunk that update is about to rebase against their origin counterpart
+/// A branch becomes a root whenever track finds no tracked ancestor for it, which is sometimes
+/// a detection mistake, so these are worth a second look before history is rewritten
+fn root_warnings(git: &dyn GitOps, dag: &dag::Dag, trunk: Option<&str>, excluded: &HashSet<dag::BranchId>) -> Vec<String> {
+    let mut roots: Vec<&dag::Branch> = dag.branches.values()
+        .filter(|branch| branch.parents.is_empty() && !excluded.contains(&branch.uid))
+        .filter(|branch| Some(branch.git_name.as_str()) != trunk)
+        .collect();
+    roots.sort_by_key(|branch| branch.uid.0);
+
+    roots.into_iter()
+        .filter(|branch| git.origin_branch_exists(branch.remote_branch_name()).unwrap_or(false))
+        .map(|branch| format!(
+            "branch '{}' is a root and will be rebased against origin/{} — is this intended? (silence with --no-root-warnings)",
+            branch.git_name, branch.remote_branch_name(),
+        ))
+        .collect()
+}
+
+/// Whether a branch has no commits beyond one of its parents, i.e. is an ancestor of it
+/// Ancestry that can't be checked counts as not redundant
+fn is_redundant(git: &dyn GitOps, dag: &dag::Dag, branch_id: dag::BranchId) -> bool {
+    let Some(branch) = dag.get_branch(&branch_id) else {
+        return false;
+    };
+    branch.parents.iter()
+        .filter_map(|parent_id| dag.get_branch(parent_id))
+        .any(|parent| git.is_ancestor(&local_branch_ref(&branch.git_name), &local_branch_ref(&parent.git_name)).unwrap_or(false))
+}
+
+/// Updated branches whose primary parent is not an ancestor of them
+/// After a successful update there shouldn't be any, so each one points at a rebase that
+/// didn't do what it reported or at a branch that moved while the update ran
+/// Other parents are left out, since update only rebases onto the primary one
+fn unverified_edges(git: &dyn GitOps, dag: &dag::Dag, updated: &HashSet<dag::BranchId>) -> Vec<String> {
+    let is_primary = |parent: &dag::BranchId, child: &dag::BranchId| dag.get_branch(child).and_then(|branch| branch.parents.first()) == Some(parent);
+    let mut edges: Vec<_> = dag.edges().filter(|(parent, child)| updated.contains(child) && is_primary(parent, child)).collect();
+    edges.sort_by_key(|(parent, child)| (child.0, parent.0));
+
+    edges.into_iter()
+        .filter_map(|(parent_id, child_id)| {
+            let parent = &dag.get_branch(&parent_id)?.git_name;
+            let child = &dag.get_branch(&child_id)?.git_name;
+            match git.is_ancestor(&local_branch_ref(parent), &local_branch_ref(child)) {
+                Ok(true) => None,
+                Ok(false) => Some(format!("'{}' does not contain its parent '{}'", child, parent)),
+                Err(e) => Some(format!("could not check whether '{}' contains '{}': {}", child, parent, e)),
+            }
+        })
+        .collect()
+}
+
+/// Deal with the PR of a branch that update removes as redundant
+/// All of its commits are in the parent, so an open PR would only linger: it is closed when
+/// `close` is set, and otherwise reported so it can be closed by hand
+fn close_redundant_pr(git: &dyn GitOps, branch_name: &str, pr_number: usize, close: bool) {
+    match git.get_pr_state(pr_number) {
+        Ok(PrState::Open) => {}
+        Ok(_) => return,
         Err(e) => {
-            eprintln!("Failed to read DAG file: {}", e);
-            std::process::exit(1);
+            println!("    Warning: PR #{} of '{}' may still be open ({}) - close it manually", pr_number, branch_name, e);
+            return;
         }
-    };
-    
-    if dag.is_empty() {
-        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
+    }
+
+    if !close {
+        println!("    Warning: PR #{} of '{}' is still open - close it manually or use --close-merged-prs", pr_number, branch_name);
         return;
     }
-    
-    // Fetch latest changes from origin
-    println!("Fetching latest changes from origin...");
-    if let Err(e) = fetch_from_origin() {
-        eprintln!("Error: Failed to fetch from origin: {}", e);
-        std::process::exit(1);
+
+    match git.close_pr(pr_number) {
+        Ok(()) => println!("    Closed PR #{} of '{}'", pr_number, branch_name),
+        Err(e) => println!("    Warning: {} - close it manually", e),
     }
-    
-    // Get branches in topological sort order
-    let sorted_branch_ids = match dag.topological_sort() {
-        Ok(ids) => ids,
+}
+
+/// The branches of a linear stack hanging off a root: each has exactly one parent and at
+/// most one child, ordered from the root's child down to the leaf
+/// Returns None if the root has parents, the stack forks, or it is too short to benefit from
+/// a single --update-refs rebase
+fn linear_stack_below(dag: &dag::Dag, root_id: dag::BranchId) -> Option<Vec<dag::BranchId>> {
+    let mut current = dag.get_branch(&root_id)?;
+    if !current.parents.is_empty() {
+        return None;
+    }
+
+    let mut stack = Vec::new();
+    while let Some(&child_id) = current.children.first() {
+        if current.children.len() > 1 {
+            return None;
+        }
+        let child = dag.get_branch(&child_id)?;
+        if child.parents.len() != 1 {
+            return None;
+        }
+        stack.push(child_id);
+        current = child;
+    }
+
+    (stack.len() >= 2).then_some(stack)
+}
+
+/// Rebase a whole linear stack onto its root in one go by rebasing the leaf with --update-refs
+/// Intermediate branches are not rebased against their origin counterparts in this mode
+/// Returns false (leaving the stack for the per-branch path) if the rebase fails
+fn rebase_stack_updating_refs(git: &dyn GitOps, dag: &mut dag::Dag, root_id: dag::BranchId, stack: &[dag::BranchId], keep_empty: bool) -> bool {
+    let Some(root_name) = dag.get_branch(&root_id).map(|b| b.git_name.clone()) else {
+        return false;
+    };
+    let Some(leaf) = stack.last().and_then(|leaf_id| dag.get_branch_mut(leaf_id)) else {
+        return false;
+    };
+
+    print!("  Rebasing stack of {} branches onto '{}' with --update-refs... ", stack.len(), root_name);
+    match git.rebase_branch_updating_refs(leaf, &root_name, keep_empty) {
+        Ok(()) => println!("✓ Success"),
         Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+            println!("✗ Failed: {}", e);
+            println!("  Falling back to rebasing the stack branch by branch");
+            // The per-branch path records the precise failure, if any
+            leaf.last_failed_rebase = None;
+            return false;
+        }
+    }
+
+    // Every branch in the stack moved together, so none of them is failing anymore
+    for branch_id in stack {
+        if let Some(branch) = dag.get_branch_mut(branch_id) {
+            branch.last_failed_rebase = None;
+        }
+    }
+    true
+}
+
+fn handle_update_command(git: &dyn GitOps, options: &UpdateOptions) -> Result<(), CommandError> {
+    ensure_has_commits(git)?;
+
+    if let Some(branch_name) = &options.interactive_rebase {
+        return interactive_rebase_onto_parent(git, branch_name);
+    }
+
+    let return_to = match &options.return_to {
+        Some(branch) => {
+            if !git.branch_exists(branch)? {
+                return Err(format!("--return-to branch '{}' does not exist", branch).into());
+            }
+            Some(branch.clone())
+        }
+        None => git.get_current_git_branch().ok(),
+    };
+
+    let result = update_all_branches(git, options);
+
+    // Check out the requested branch again, even if the update failed
+    if let Some(branch) = return_to {
+        if git.get_current_git_branch().ok().as_deref() != Some(branch.as_str()) {
+            if let Err(e) = git.checkout_branch(&branch) {
+                println!("Warning: failed to check out '{}' after the update: {}", branch, e);
+            }
+        }
+    }
+
+    result
+}
+
+/// Name of a branch's primary (first) parent, if it has a tracked one
+fn primary_parent_name(dag: &dag::Dag, branch_id: dag::BranchId) -> Option<String> {
+    dag.get_branch(&branch_id)
+        .and_then(|branch| branch.parents.first())
+        .and_then(|parent_id| dag.get_branch(parent_id))
+        .map(|parent| parent.git_name.clone())
+}
+
+/// Interactively rebase one tracked branch onto its primary parent
+fn interactive_rebase_onto_parent(git: &dyn GitOps, branch_name: &str) -> Result<(), CommandError> {
+    let mut dag = load_dag()?;
+    let branch_id = find_tracked_branch(&dag, branch_name)?;
+    let parent_name = primary_parent_name(&dag, branch_id)
+        .ok_or_else(|| format!("Branch '{}' has no parent to rebase onto", branch_name))?;
+
+    let branch = dag.get_branch_mut(&branch_id).expect("tracked branch is in the DAG");
+    let outcome = git.interactive_rebase_branch(branch, &parent_name)
+        .map_err(|e| format!("Interactive rebase of '{}' onto '{}' failed: {}", branch_name, parent_name, e))?;
+
+    match outcome {
+        InteractiveRebase::Completed(commit) => {
+            // Persist the cleared failed-rebase marker
+            save_dag(&dag)?;
+            println!("Rebased '{}' onto '{}', now at {}", branch_name, parent_name, &commit[..commit.len().min(7)]);
+        }
+        InteractiveRebase::Stopped => {
+            println!("The rebase of '{}' stopped; finish it with 'git rebase --continue' or 'git rebase --abort'", branch_name);
+        }
+    }
+    Ok(())
+}
+
+fn update_all_branches(git: &dyn GitOps, options: &UpdateOptions) -> Result<(), CommandError> {
+    println!("Starting update process...");
+    
+    // Load existing DAG from file
+    let mut dag = load_dag()?;
+    
+    if dag.is_empty() {
+        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
+        return Ok(());
+    }
+
+    // A trunk pinned with set-trunk wins over the configured or default one
+    let pinned_options;
+    let options = match dag.trunk_branch() {
+        Some(trunk) => {
+            pinned_options = UpdateOptions { trunk: Some(trunk.git_name.clone()), ..options.clone() };
+            &pinned_options
         }
+        None => options,
     };
+
+    // Rebasing a child onto a parent that wasn't updated could be wrong, so descendants of
+    // excluded branches are left alone too
+    let mut excluded: HashSet<dag::BranchId> = HashSet::new();
+    for branch_name in &options.exclude {
+        let branch_id = find_tracked_branch(&dag, branch_name)?;
+        excluded.extend(dag.get_recursive_children(branch_id));
+    }
+    
+    // With --current-stack, branches outside the current branch's stack aren't touched or reported
+    let in_scope = if options.current_stack { Some(current_stack(git, &dag)?) } else { None };
+    let out_of_scope: HashSet<dag::BranchId> = dag.branches.keys()
+        .filter(|id| in_scope.as_ref().is_some_and(|stack| !stack.contains(id)))
+        .copied()
+        .collect();
+
+    // Fetch latest changes from origin
+    println!("Fetching latest changes from origin...");
+    git.fetch_from_origin().map_err(|e| format!("Failed to fetch from origin: {}", e))?;
+
+    if options.root_warnings {
+        let ignored: HashSet<dag::BranchId> = excluded.union(&out_of_scope).copied().collect();
+        for warning in root_warnings(git, &dag, options.trunk.as_deref(), &ignored) {
+            println!("Warning: {}", warning);
+        }
+    }
+
+    // Make sure the --onto ref exists before rebasing anything onto it
+    if let Some(onto) = &options.onto {
+        git.get_branch_commit(onto).map_err(|_| format!("--onto ref '{}' does not resolve to a commit", onto))?;
+    }
+    
+    // Get branches in topological sort order
+    let mut sorted_branch_ids = dag.topological_sort()?;
+    sorted_branch_ids.retain(|id| !out_of_scope.contains(id));
     
     // Track branches that failed rebase (and their children should be skipped)
-    let mut failed_branches: HashSet<dag::BranchId> = HashSet::new();
-    let mut skipped_branches: HashSet<dag::BranchId> = HashSet::new();
+    let mut progress = UpdateProgress::default();
     
     println!("Processing {} branches in topological order...", sorted_branch_ids.len());
     
+    let update_refs = options.update_refs && {
+        let supported = git.git_supports_update_refs();
+        if !supported {
+            println!("This git version doesn't support --update-refs (needs 2.38+), rebasing branch by branch");
+        }
+        supported
+    };
+    // Branches already moved by a --update-refs rebase of their stack
+    let mut rebased_with_stack: HashSet<dag::BranchId> = HashSet::new();
+
+    // Branches updated successfully, by this run or (with --resume) by the incomplete run before it
+    let mut completed: HashSet<dag::BranchId> = HashSet::new();
+    if options.resume {
+        match read_update_state().map_err(|e| format!("Failed to read update state: {}", e))? {
+            Some(state) => completed = state.completed,
+            None => println!("No incomplete update to resume, updating all branches"),
+        }
+    }
+    let mut resumed_count = 0;
+
     // Process each branch in topological order
-    for &branch_id in &sorted_branch_ids {
-        update_branch(&mut dag, branch_id, &mut failed_branches, &mut skipped_branches);
+    let total = sorted_branch_ids.len();
+    for (index, &branch_id) in sorted_branch_ids.iter().enumerate() {
+        if rebased_with_stack.contains(&branch_id) {
+            continue;
+        }
+        if completed.contains(&branch_id) {
+            let branch_name = dag.get_branch(&branch_id).map(|b| b.git_name.as_str()).unwrap_or("unknown");
+            println!("[{}/{}] Skipping '{}' (already updated by the previous run)", index + 1, total, branch_name);
+            resumed_count += 1;
+            continue;
+        }
+        if excluded.contains(&branch_id) {
+            let branch_name = dag.get_branch(&branch_id).map(|b| b.git_name.as_str()).unwrap_or("unknown");
+            println!("[{}/{}] Skipping '{}' (excluded)", index + 1, total, branch_name);
+            progress.skipped.insert(branch_id);
+            continue;
+        }
+        update_branch(git, &mut dag, branch_id, (index + 1, total), options, &mut progress);
+
+        if !progress.failed.contains(&branch_id) && !progress.skipped.contains(&branch_id) {
+            completed.insert(branch_id);
+        }
+
+        if update_refs && !progress.failed.contains(&branch_id) {
+            // Stacks with PRs go branch by branch so that open PRs are checked and skipped, and
+            // stacks with excluded branches so that those are left alone
+            let stack = linear_stack_below(&dag, branch_id).filter(|stack| {
+                let has_open_pr = !options.allow_pr_rebase && stack.iter().any(|id| dag.get_branch(id).is_none_or(|b| b.pr_number.is_some()));
+                !has_open_pr && !stack.iter().any(|id| excluded.contains(id))
+            });
+            if let Some(stack) = stack {
+                if rebase_stack_updating_refs(git, &mut dag, branch_id, &stack, options.keep_empty) {
+                    completed.extend(stack.iter().copied());
+                    rebased_with_stack.extend(stack);
+                }
+            }
+        }
+
+        if options.fail_fast && !progress.failed.is_empty() {
+            // The failed rebase was already aborted and recorded, the rest is left untouched
+            let remaining = sorted_branch_ids[index + 1..].iter()
+                .filter(|id| !rebased_with_stack.contains(id));
+            progress.skipped.extend(remaining);
+            println!("Stopping after the first failure (--fail-fast)");
+            break;
+        }
     }
     
     // Save updated DAG back to file (to persist any last_failed_rebase updates)
-    match write_dag_to_file(&dag) {
-        Ok(()) => {},
-        Err(e) => {
-            eprintln!("Failed to write DAG file: {}", e);
-            std::process::exit(1);
-        }
-    }
+    save_dag(&dag)?;
+
+    // Checked before the state takes ownership of the completed branches
+    let unverified = if options.verify { unverified_edges(git, &dag, &completed) } else { vec![] };
+
+    // Remember what was done so that a failed update can be resumed
+    let state_result = if progress.failed.is_empty() {
+        clear_update_state()
+    } else {
+        completed.retain(|id| dag.contains_branch(id));
+        write_update_state(&UpdateState { completed })
+    };
+    state_result.map_err(|e| format!("Failed to write update state: {}", e))?;
     
     // Summary
     let total_branches = sorted_branch_ids.len();
-    let failed_count = failed_branches.len();
-    let skipped_count = skipped_branches.len();
-    let success_count = total_branches - failed_count - skipped_count;
+    let failed_count = progress.failed.len();
+    let skipped_count = progress.skipped.len();
+    let success_count = total_branches - failed_count - skipped_count - resumed_count;
     
     println!();
     println!("Update completed:");
     println!("  ✓ {} branches successfully updated", success_count);
+    if resumed_count > 0 {
+        println!("  ✓ {} branches already updated by the previous run", resumed_count);
+    }
     println!("  ✗ {} branches failed", failed_count);
-    println!("  - {} branches skipped (due to parent failures)", skipped_count);
+    println!("  - {} branches skipped (due to parent failures, open PRs, --exclude or --fail-fast)", skipped_count);
+    if let Some(error) = progress.pr_check_errors.first() {
+        println!();
+        println!(
+            "Warning: skipped {} branches because their PR state couldn't be checked: {}",
+            progress.pr_check_errors.len(), error
+        );
+        println!("Fix the GitHub CLI (gh) setup, or run with --allow-pr-rebase to rebase them anyway.");
+    }
     
     if failed_count > 0 || skipped_count > 0 {
         println!();
         println!("Some branches had issues. Check the output above for details.");
     }
+    if failed_count > 0 {
+        println!("After fixing them, run 'dagit update --resume' to skip the branches that were already updated.");
+    }
+
+    if options.verify {
+        println!();
+        if unverified.is_empty() {
+            println!("Verified that every updated branch contains its primary parent");
+        }
+        for problem in &unverified {
+            println!("Verification failed: {}", problem);
+        }
+    }
+
+    if failed_count > 0 {
+        return Err(CommandError {
+            message: format!("{} branches failed to update", failed_count),
+            failed_branches: branch_names(&dag, &progress.failed),
+            skipped_branches: branch_names(&dag, &progress.skipped),
+        });
+    }
+    if !unverified.is_empty() {
+        return Err(format!("{} parent relationships failed verification", unverified.len()).into());
+    }
+
+    Ok(())
+}
+
+/// Options controlling how `dagit submit` opens PRs
+struct SubmitOptions {
+    /// Target the default branch instead of each branch's parent
+    flat: bool,
+    /// Only print the gh commands
+    dry_run: bool,
+    /// Push head and base branches that origin doesn't have yet
+    push: bool,
+    /// Most PRs created at the same time, see submit_waves
+    concurrency: usize,
+    /// Reviewers, labels and title template for the new PRs
+    metadata: PrMetadata,
 }
 
-fn handle_submit_command() {
+fn handle_submit_command(git: &dyn GitOps, options: &SubmitOptions) -> Result<(), CommandError> {
+    ensure_has_commits(git)?;
+    if options.concurrency == 0 {
+        return Err("--concurrency must be at least 1".into());
+    }
+
     println!("Starting submit process...");
 
     // Load existing DAG from file
-    let mut dag = match read_dag_from_file() {
-        Ok(dag) => dag,
-        Err(e) => {
-            eprintln!("Failed to read DAG file: {}", e);
-            std::process::exit(1);
+    let mut dag = load_dag()?;
+
+    if dag.is_empty() {
+        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
+        return Ok(());
+    }
+
+    // Parents before children, in the same order as the previous submit where possible
+    let submit_order = dag.reconcile_submit_order()?;
+
+    // In flat mode every PR targets the default branch instead of the branch's parent
+    let base_override = if options.flat {
+        let default_branch = match dag.trunk_branch() {
+            Some(trunk) => trunk.git_name.clone(),
+            None => git.get_default_branch("origin")?,
+        };
+        println!("Flat mode: all PRs will target '{}'", default_branch);
+        Some(default_branch)
+    } else {
+        None
+    };
+
+    if options.dry_run {
+        print_submit_plan(git, &dag, &submit_order, base_override.as_deref(), options)?;
+        return Ok(());
+    }
+
+    println!("Processing {} branches in submit order for PR creation...", submit_order.len());
+
+    let mut pr_created_count = 0;
+    let mut pr_skipped_count = 0;
+    let mut pr_error_count = 0;
+    let mut error_branches = Vec::new();
+
+    // Used to link created PRs; remotes that aren't on a hosting service just don't get links
+    let repo_slug = git.get_remote_url().ok().and_then(|url| parse_remote_url(&url));
+
+    // Waves go parents first, so every base has been pushed by the time its children's PRs open
+    for wave in submit_waves(&dag, &submit_order, options.concurrency) {
+        let results = create_prs_concurrently(git, &dag, &wave, base_override.as_deref(), options);
+
+        for (branch_id, result) in wave.into_iter().zip(results) {
+            let Some(branch) = dag.get_branch_mut(&branch_id) else {
+                continue;
+            };
+            println!("*** Processing branch '{}' ***", branch.git_name);
+
+            match result {
+                Ok(Some(pr_number)) => {
+                    branch.pr_number = Some(pr_number);
+                    match &repo_slug {
+                        Some(slug) => println!("  ✓ Created PR #{}: {}", pr_number, slug.pull_request_url(pr_number)),
+                        None => println!("  ✓ Created PR #{}", pr_number),
+                    }
+                    pr_created_count += 1;
+                }
+                Ok(None) => {
+                    println!("  - Skipped (already exists or no parent)");
+                    pr_skipped_count += 1;
+                }
+                Err(e) => {
+                    println!("  ✗ Error: {}", e);
+                    pr_error_count += 1;
+                    error_branches.push(branch.git_name.clone());
+                }
+            }
+        }
+    }
+
+    // Save updated DAG back to file (to persist pr_number updates)
+    save_dag(&dag)?;
+
+    // Summary
+    println!();
+    println!("Submit completed:");
+    println!("  ✓ {} PRs created", pr_created_count);
+    println!("  - {} PRs skipped (already exist or no parent)", pr_skipped_count);
+    println!("  ✗ {} PR creation errors", pr_error_count);
+
+    if pr_error_count > 0 {
+        println!();
+        println!("Some branches had PR creation errors. Check the output above for details.");
+
+        error_branches.sort();
+        return Err(CommandError {
+            message: format!("{} PRs failed to be created", pr_error_count),
+            failed_branches: error_branches,
+            skipped_branches: Vec::new(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Split the submit order into waves of branches whose PRs can be created at the same time
+/// Branches at the same depth can't be ancestors of each other, so each wave holds up to
+/// `concurrency` branches of one depth, in submit order; shallower waves come first
+/// With a concurrency of 1 this is just the submit order, one branch per wave
+fn submit_waves(dag: &dag::Dag, order: &[dag::BranchId], concurrency: usize) -> Vec<Vec<dag::BranchId>> {
+    if concurrency <= 1 {
+        return order.iter().map(|&id| vec![id]).collect();
+    }
+
+    let mut by_depth: BTreeMap<usize, Vec<dag::BranchId>> = BTreeMap::new();
+    for &branch_id in order {
+        let depth = dag.get_branch_depth(branch_id).unwrap_or(0);
+        by_depth.entry(depth).or_default().push(branch_id);
+    }
+
+    by_depth.into_values()
+        .flat_map(|ids| ids.chunks(concurrency).map(<[dag::BranchId]>::to_vec).collect::<Vec<_>>())
+        .collect()
+}
+
+/// Create the PRs of one wave, each on its own thread when there are several
+/// Every thread works on its own copy of the DAG; only the resulting PR numbers are returned,
+/// in the order of `wave`, to be recorded in the real DAG by the caller
+fn create_prs_concurrently(git: &dyn GitOps, dag: &dag::Dag, wave: &[dag::BranchId], base_override: Option<&str>, options: &SubmitOptions) -> Vec<Result<Option<usize>, String>> {
+    let create = |branch_id: dag::BranchId| {
+        let mut dag = dag.clone();
+        git.create_pr_for_branch(branch_id, &mut dag, base_override, &options.metadata, options.push)
+    };
+
+    if let [branch_id] = wave {
+        return vec![create(*branch_id)];
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = wave.iter().map(|&branch_id| scope.spawn(move || create(branch_id))).collect();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err("PR creation panicked".to_string())))
+            .collect()
+    })
+}
+
+/// Print the `gh pr create` invocation submit would run for each branch, without running it
+fn handle_preview_pr_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let dag = load_dag()?;
+    let title_template = git.get_pr_title_template();
+
+    if dag.is_empty() {
+        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
+        return Ok(());
+    }
+
+    for branch in dag.iter_topological()? {
+        println!("*** Branch '{}' ***", branch.git_name);
+
+        if let Some(pr_number) = branch.pr_number {
+            println!("  PR #{} already exists", pr_number);
+            continue;
+        }
+
+        match resolve_pr_base(branch.uid, &dag, None)? {
+            Some(base) => {
+                println!("  Base:  {}", git::remote_name_of(&dag, &base));
+                println!("  Head:  {}", branch.remote_branch_name());
+                println!("  Title: {}", pr_title(&branch.git_name, &base, title_template.as_deref()));
+            }
+            None => println!("  - No PR (branch has no parent)"),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_submit_plan(git: &dyn GitOps, dag: &dag::Dag, order: &[dag::BranchId], base_override: Option<&str>, options: &SubmitOptions) -> Result<(), CommandError> {
+    println!("Dry run: no PRs will be created");
+
+    let mut would_create_count = 0;
+    let mut would_skip_count = 0;
+
+    for branch in order.iter().filter_map(|id| dag.get_branch(id)) {
+        println!("*** Processing branch '{}' ***", branch.git_name);
+
+        match resolve_pr_base(branch.uid, dag, base_override) {
+            Ok(Some(base)) => {
+                let base_remote_name = git::remote_name_of(dag, &base);
+                for (name, remote_name) in [(base.as_str(), base_remote_name.as_str()), (branch.git_name.as_str(), branch.remote_branch_name())] {
+                    if git.origin_branch_exists(remote_name).unwrap_or(false) {
+                        continue;
+                    }
+                    let is_base = name == base;
+                    if options.push && (!is_base || git.branch_exists(name).unwrap_or(false)) {
+                        println!("  Would run: git push --set-upstream origin {}:{}", local_branch_ref(name), remote_name);
+                    } else if is_base {
+                        println!("  ✗ {}", pr_base_missing_message(name, remote_name));
+                    } else {
+                        println!("  ✗ '{}' is not pushed to origin; run without --no-push or push it manually", remote_name);
+                    }
+                }
+                let args = build_pr_create_args(branch.remote_branch_name(), &base_remote_name, &options.metadata, &git.pr_body(&branch.git_name, &base));
+                println!("  Would run: {}", format_command_line("gh", &args));
+                if let Some(base) = base_override {
+                    println!("  (base overridden to '{}' by --flat)", base);
+                }
+                would_create_count += 1;
+            }
+            Ok(None) => {
+                println!("  - Would skip (already exists or no parent)");
+                would_skip_count += 1;
+            }
+            Err(e) => println!("  ✗ Error: {}", e),
+        }
+    }
+
+    println!();
+    println!("Dry run completed:");
+    println!("  {} PRs would be created", would_create_count);
+    println!("  {} PRs would be skipped (already exist or no parent)", would_skip_count);
+
+    Ok(())
+}
+
+/// Render a command line so that it can be copy-pasted into a shell
+fn format_command_line(program: &str, args: &[String]) -> String {
+    let mut parts = vec![program.to_string()];
+    for arg in args {
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"$`\\<>|&;*?()".contains(c)) {
+            parts.push(format!("'{}'", arg.replace('\'', "'\\''")));
+        } else {
+            parts.push(arg.clone());
+        }
+    }
+    parts.join(" ")
+}
+
+fn handle_restore_command() -> Result<(), CommandError> {
+    restore_dag_from_backup().map_err(|e| format!("Failed to restore DAG file: {}", e))?;
+    println!("Restored the previous DAG (run 'dagit restore' again to undo)");
+    Ok(())
+}
+
+fn handle_set_primary_command(child_name: &str, parent_name: &str) -> Result<(), CommandError> {
+    // Load existing DAG from file
+    let mut dag = load_dag()?;
+
+    let child_id = find_tracked_branch(&dag, child_name)?;
+    let parent_id = find_tracked_branch(&dag, parent_name)?;
+
+    dag.set_primary_parent(child_id, parent_id)?;
+
+    // Save updated DAG back to file
+    save_dag(&dag)?;
+
+    println!("'{}' is now the primary parent of '{}'", parent_name, child_name);
+    Ok(())
+}
+
+fn handle_move_command(git: &dyn GitOps, branch_name: &str, new_parent_name: &str) -> Result<(), CommandError> {
+    ensure_has_commits(git)?;
+
+    // Load existing DAG from file
+    let mut dag = load_dag()?;
+
+    let branch_id = find_tracked_branch(&dag, branch_name)?;
+    let new_parent_id = find_tracked_branch(&dag, new_parent_name)?;
+
+    dag.reparent(branch_id, new_parent_id)?;
+
+    // Rebase the moved subtree parents-first so each branch lands on its already rebased parent
+    let subtree = dag.get_recursive_children(branch_id);
+    let order: Vec<dag::BranchId> = dag.topological_sort()?
+        .into_iter()
+        .filter(|id| subtree.contains(id))
+        .collect();
+
+    // Where each branch was before the move, so that a failure can put them all back
+    let mut original_commits = Vec::new();
+    for &id in &order {
+        let name = dag.get_branch(&id).map(|branch| branch.git_name.clone()).ok_or("Moved branch is missing from the DAG")?;
+        let commit = git.get_branch_commit(&name).map_err(|e| format!("Failed to read the commit of '{}': {}", name, e))?;
+        original_commits.push((name, commit));
+    }
+
+    for (index, &id) in order.iter().enumerate() {
+        let parent_name = primary_parent_name(&dag, id)
+            .ok_or("Moved branch lost its parent in the DAG")?;
+
+        let Some(branch_mut) = dag.get_branch_mut(&id) else {
+            continue;
+        };
+        let moved_name = branch_mut.git_name.clone();
+        print!("Rebasing '{}' onto '{}'... ", moved_name, parent_name);
+
+        if let Err(e) = git.rebase_branch(branch_mut, &parent_name, false) {
+            println!("✗ Failed: {}", e);
+
+            // The failed rebase was already aborted; undo the ones before it and leave the DAG
+            // file alone, so that git and the DAG are both as they were before the move
+            for (name, commit) in original_commits[..index].iter().rev() {
+                if let Err(reset_error) = git.reset_branch(name, commit) {
+                    println!("Warning: failed to reset '{}' back to {}: {}", name, commit, reset_error);
+                }
+            }
+
+            let skipped: HashSet<dag::BranchId> = order[index + 1..].iter().copied().collect();
+            return Err(CommandError {
+                message: format!("Move aborted: rebasing '{}' onto '{}' failed; the branches and the DAG were left unchanged", moved_name, parent_name),
+                failed_branches: vec![moved_name],
+                skipped_branches: branch_names(&dag, &skipped),
+            });
+        }
+        println!("✓ Success");
+    }
+
+    save_dag(&dag)?;
+    println!("Moved '{}' onto '{}'", branch_name, new_parent_name);
+    Ok(())
+}
+
+fn handle_merge_nodes_command(src_name: &str, dst_name: &str) -> Result<(), CommandError> {
+    // Load existing DAG from file
+    let mut dag = load_dag()?;
+
+    let src_id = find_tracked_branch(&dag, src_name)?;
+    let dst_id = find_tracked_branch(&dag, dst_name)?;
+
+    dag.merge_into(src_id, dst_id)?;
+
+    // Save updated DAG back to file
+    save_dag(&dag)?;
+
+    println!("Merged '{}' into '{}'", src_name, dst_name);
+    Ok(())
+}
+
+fn handle_failed_command() -> Result<(), CommandError> {
+    let dag = load_dag()?;
+
+    let mut failed: Vec<(&str, String)> = dag.branches.values()
+        .filter_map(|branch| branch.last_failed_rebase.as_ref().map(|failed| (branch.git_name.as_str(), describe_failed_rebase(failed, DEFAULT_HASH_ABBREV))))
+        .collect();
+    failed.sort();
+
+    if failed.is_empty() {
+        println!("No branches with failed rebases");
+        return Ok(());
+    }
+
+    for (branch_name, target) in &failed {
+        println!("{} (failed to rebase onto '{}')", branch_name, target);
+    }
+    Err(CommandError {
+        message: format!("{} branches have failed rebases", failed.len()),
+        failed_branches: failed.iter().map(|(branch_name, _)| branch_name.to_string()).collect(),
+        skipped_branches: Vec::new(),
+    })
+}
+
+fn handle_path_command() -> Result<(), CommandError> {
+    let path = get_dag_file_path().map_err(|e| format!("Failed to locate the DAG file: {}", e))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn handle_prune_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let mut dag = load_dag()?;
+
+    let existing: HashSet<String> = git.get_all_branches()
+        .map_err(|e| format!("Failed to list git branches: {}", e))?
+        .into_iter()
+        .collect();
+    let pruned = dag.retain_existing(&existing);
+    if pruned.is_empty() {
+        println!("All tracked branches still exist in git");
+        return Ok(());
+    }
+
+    save_dag(&dag)?;
+
+    for name in &pruned {
+        println!("Pruned '{}'", name);
+    }
+    println!("Pruned {} branches that no longer exist in git", pruned.len());
+    Ok(())
+}
+
+fn handle_reconstruct_command(git: &dyn GitOps, all: bool, yes: bool) -> Result<(), CommandError> {
+    ensure_has_commits(git)?;
+
+    let branch_names = if all {
+        git.get_all_branches().map_err(|e| format!("Failed to list git branches: {}", e))?
+    } else {
+        load_dag()
+            .map_err(|e| format!("{} (use --all to rebuild from every local branch)", e.message))?
+            .get_tracked_branch_names()
+    };
+    if branch_names.is_empty() {
+        return Err("No branches to rebuild the DAG from (use --all to rebuild from every local branch)".into());
+    }
+
+    let dag = reconstruct_dag(git, &branch_names)?;
+    println!("Rebuilt DAG from git ancestry ({} branches):", dag.len());
+    for branch in dag.iter_topological()? {
+        match primary_parent_name(&dag, branch.uid) {
+            Some(parent) => println!("  '{}' on top of '{}'", branch.git_name, parent),
+            None => println!("  '{}' (root)", branch.git_name),
+        }
+    }
+
+    if !yes && !confirm("Replace the DAG file with this DAG? (the current one is kept as a backup)") {
+        println!("Not writing the rebuilt DAG");
+        return Ok(());
+    }
+
+    save_dag(&dag)?;
+    println!("Wrote the rebuilt DAG");
+    Ok(())
+}
+
+/// Build a fresh DAG of the given branches, with each one's parent detected from git the same
+/// way track does (see find_closest_parent)
+/// Branches are created in name order, so rebuilding from the same branches and commits
+/// always gives the same DAG; PRs, notes and other metadata are not recovered
+fn reconstruct_dag(git: &dyn GitOps, branch_names: &[String]) -> Result<dag::Dag, CommandError> {
+    let mut names = branch_names.to_vec();
+    names.sort();
+    names.dedup();
+
+    let mut dag = dag::Dag::new();
+    for name in &names {
+        dag.create_branch(name.clone());
+    }
+
+    for name in &names {
+        if let Some((parent, _)) = git.find_closest_parent(name, &names, None)? {
+            if let Err(e) = dag.add_parent_child_relationship(name, &parent) {
+                println!("Warning: not making '{}' a parent of '{}': {}", parent, name, e);
+            }
         }
+    }
+
+    Ok(dag)
+}
+
+fn handle_diff_command(git: &dyn GitOps, branch_name: Option<String>) -> Result<(), CommandError> {
+    let (branch_name, parent_name) = branch_and_primary_parent(git, branch_name, "diff against")?;
+
+    git.show_diff_since(&parent_name, &branch_name)
+        .map_err(|e| format!("Failed to diff '{}' against '{}': {}", branch_name, parent_name, e).into())
+}
+
+fn handle_log_command(git: &dyn GitOps, branch_name: Option<String>, author: Option<&str>) -> Result<(), CommandError> {
+    let (branch_name, parent_name) = branch_and_primary_parent(git, branch_name, "show the log since")?;
+
+    git.show_log_since(&parent_name, &branch_name, author)
+        .map_err(|e| format!("Failed to show the log of '{}' since '{}': {}", branch_name, parent_name, e).into())
+}
+
+/// The given (or current) tracked branch and its primary parent, for commands that look at what
+/// a branch added on top of its parent; `purpose` completes the error for root branches
+fn branch_and_primary_parent(git: &dyn GitOps, branch_name: Option<String>, purpose: &str) -> Result<(String, String), CommandError> {
+    ensure_has_commits(git)?;
+
+    let branch_name = match branch_name {
+        Some(name) => name,
+        None => git.get_current_git_branch()?,
     };
+    let dag = load_dag()?;
+
+    let branch_id = find_tracked_branch(&dag, &branch_name)?;
+    let parent_name = primary_parent_name(&dag, branch_id)
+        .ok_or_else(|| format!("Branch '{}' has no tracked parent to {}", branch_name, purpose))?;
+    Ok((branch_name, parent_name))
+}
+
+/// The post-checkout hook and its current content, if it exists
+fn read_post_checkout_hook(git: &dyn GitOps) -> Result<(std::path::PathBuf, Option<String>), CommandError> {
+    let hook_path = git.get_hooks_dir()?.join("post-checkout");
+    match std::fs::read_to_string(&hook_path) {
+        Ok(hook) => Ok((hook_path, Some(hook))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((hook_path, None)),
+        Err(e) => Err(format!("Failed to read {}: {}", hook_path.display(), e).into()),
+    }
+}
+
+fn handle_install_hooks_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let (hook_path, existing) = read_post_checkout_hook(git)?;
+    let Some(hook) = git::with_dagit_hook_section(existing.as_deref()) else {
+        println!("The dagit post-checkout hook is already installed in {}", hook_path.display());
+        return Ok(());
+    };
+
+    let write_hook = || -> io::Result<()> {
+        if let Some(hooks_dir) = hook_path.parent() {
+            std::fs::create_dir_all(hooks_dir)?;
+        }
+        std::fs::write(&hook_path, hook)?;
+        // git skips hooks that aren't executable
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = std::fs::metadata(&hook_path)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            std::fs::set_permissions(&hook_path, permissions)?;
+        }
+        Ok(())
+    };
+    write_hook().map_err(|e| format!("Failed to write {}: {}", hook_path.display(), e))?;
+
+    match existing {
+        Some(_) => println!("Added the dagit section to the existing post-checkout hook in {}", hook_path.display()),
+        None => println!("Installed the dagit post-checkout hook in {}", hook_path.display()),
+    }
+    Ok(())
+}
+
+fn handle_uninstall_hooks_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let (hook_path, existing) = read_post_checkout_hook(git)?;
+    let Some(rest) = existing.as_deref().and_then(git::without_dagit_hook_section) else {
+        println!("The dagit post-checkout hook is not installed");
+        return Ok(());
+    };
+
+    let result = if rest.is_empty() {
+        std::fs::remove_file(&hook_path)
+    } else {
+        std::fs::write(&hook_path, rest)
+    };
+    result.map_err(|e| format!("Failed to update {}: {}", hook_path.display(), e))?;
+
+    println!("Removed the dagit post-checkout hook from {}", hook_path.display());
+    Ok(())
+}
+
+fn handle_schema_command() -> Result<(), CommandError> {
+    let schema = serde_json::to_string_pretty(&serde::dag_json_schema())
+        .map_err(|e| format!("Failed to render the schema: {}", e))?;
+    println!("{}", schema);
+    Ok(())
+}
+
+fn handle_summary_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let dag = load_dag()?;
+
+    let with_pr = dag.branches.values().filter(|branch| branch.pr_number.is_some()).count();
+    let (open_prs, pr_check_errors) = count_open_prs(git, &dag);
+    let status_counts = dag.status_counts(|branch| branch_status(git, branch, &dag));
+    let out_of_date = status_counts.get(&BranchStatus::OutOfDate).copied().unwrap_or(0);
+    let failed = status_counts.get(&BranchStatus::FailedUpdate).copied().unwrap_or(0);
+    let max_depth = dag.branch_depths().and_then(|depths| depths.into_values().max()).unwrap_or(0);
+
+    println!("Tracked branches: {}", dag.len());
+    println!("Recorded PRs:     {}", with_pr);
+    match pr_check_errors.first() {
+        None => println!("Open PRs:         {}", open_prs),
+        Some(error) => println!("Open PRs:         {} ({} couldn't be checked: {})", open_prs, pr_check_errors.len(), error),
+    }
+    println!("Out of date:      {}", out_of_date);
+    println!("Failed rebases:   {}", failed);
+    println!("Max stack depth:  {}", max_depth);
+    Ok(())
+}
+
+/// How many of the recorded PRs are still open, and why each one that couldn't be checked wasn't
+fn count_open_prs(git: &dyn GitOps, dag: &dag::Dag) -> (usize, Vec<String>) {
+    let mut open = 0;
+    let mut errors = Vec::new();
+    for pr_number in dag.branches.values().filter_map(|branch| branch.pr_number) {
+        match git.get_pr_state(pr_number) {
+            Ok(PrState::Open) => open += 1,
+            Ok(_) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    (open, errors)
+}
+
+fn handle_plan_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let dag = load_dag()?;
+    if dag.is_empty() {
+        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
+        return Ok(());
+    }
+
+    println!("Update plan, parents first (origin as last fetched):");
+    for (index, line) in update_plan(git, &dag)?.iter().enumerate() {
+        println!("  {}. {}", index + 1, line);
+    }
+    Ok(())
+}
+
+/// One line per branch, in topological order, describing the steps update_branch would take
+/// Only local refs are consulted: origin is as of the last fetch, and PRs aren't looked up, so
+/// a branch with a PR is only flagged as possibly skipped
+fn update_plan(git: &dyn GitOps, dag: &dag::Dag) -> Result<Vec<String>, CommandError> {
+    let trunk = dag.trunk_branch().map(|trunk| trunk.git_name.clone()).or_else(|| git.get_trunk_branch());
+    let contains = |ancestor: &str, branch: &str| git.is_ancestor(ancestor, &local_branch_ref(branch)).unwrap_or(false);
+
+    // Branches expected to move, whose children then have to be rebased after them
+    let mut moving: HashSet<dag::BranchId> = HashSet::new();
+    let mut plan = Vec::new();
+    for branch_id in dag.topological_sort()? {
+        let branch = &dag.branches[&branch_id];
+        let remote = branch.remote_branch_name();
+        let origin_ref = format!("refs/remotes/origin/{}", remote);
+        let has_origin = git.origin_branch_exists(remote).unwrap_or(false);
+        let mut steps = Vec::new();
+
+        if let Some(pr_number) = branch.pr_number {
+            steps.push(format!("skipped if PR #{} is open (unless --allow-pr-rebase)", pr_number));
+        }
+
+        if trunk.as_deref() == Some(branch.git_name.as_str()) {
+            if !has_origin {
+                steps.push(format!("trunk, no origin/{} to fast-forward to", remote));
+            } else if contains(&origin_ref, &branch.git_name) {
+                steps.push(format!("trunk, up to date with origin/{}", remote));
+            } else {
+                steps.push(format!("fast-forward to origin/{}", remote));
+                moving.insert(branch_id);
+            }
+        } else if has_origin {
+            steps.push(format!("rebase against origin/{}", remote));
+        }
+
+        match branch.parents.first().and_then(|parent_id| dag.get_branch(parent_id)) {
+            Some(parent) if moving.contains(&parent.uid) => {
+                steps.push(format!("rebase onto '{}' after it is updated (skipped if that fails)", parent.git_name));
+                moving.insert(branch_id);
+            }
+            Some(parent) if contains(&local_branch_ref(&parent.git_name), &branch.git_name) => {
+                steps.push(format!("already on top of '{}'", parent.git_name));
+            }
+            Some(parent) => {
+                steps.push(format!("rebase onto '{}'", parent.git_name));
+                moving.insert(branch_id);
+            }
+            None if trunk.as_deref() != Some(branch.git_name.as_str()) => steps.push("root, no parent to rebase onto".to_string()),
+            None => {}
+        }
+        if branch.parents.len() > 1 {
+            steps.push("only the primary parent is used".to_string());
+        }
+
+        plan.push(format!("{}: {}", branch.git_name, steps.join(", ")));
+    }
+    Ok(plan)
+}
+
+fn handle_doctor_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let dag = load_dag()?;
+
+    let mut problems = dag.validate();
+    problems.extend(ancestry_problems(git, &dag));
+    problems.extend(same_tip_problems(git, &dag));
+    if problems.is_empty() {
+        println!("No problems found in the DAG ({} branches)", dag.len());
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("✗ {}", problem);
+    }
+    Err(format!("{} problems found in the DAG", problems.len()).into())
+}
+
+/// Groups of tracked branches that point at the same commit, which usually means one of them
+/// was renamed in git and both names got tracked
+/// Branches whose commit can't be resolved are left out; ancestry_problems covers those
+fn same_tip_problems(git: &dyn GitOps, dag: &dag::Dag) -> Vec<String> {
+    let mut by_commit: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for branch in dag.branches.values() {
+        if let Ok(commit) = git.get_branch_commit(&local_branch_ref(&branch.git_name)) {
+            by_commit.entry(commit).or_default().push(branch.git_name.clone());
+        }
+    }
+
+    by_commit.into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(commit, mut names)| {
+            names.sort();
+            let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+            format!(
+                "{} point at the same commit {} and may be duplicates (combine them with dagit merge-nodes)",
+                quoted.join(", "), &commit[..commit.len().min(DEFAULT_HASH_ABBREV)],
+            )
+        })
+        .collect()
+}
+
+/// Edges of the DAG that git disagrees with: the recorded parent isn't an ancestor of the child
+/// Branches whose last update failed are left out, since that is already known and reported
+fn ancestry_problems(git: &dyn GitOps, dag: &dag::Dag) -> Vec<String> {
+    let mut branches: Vec<&dag::Branch> = dag.branches.values().collect();
+    branches.sort_by_key(|branch| branch.uid.0);
+
+    let mut problems = Vec::new();
+    for branch in branches.into_iter().filter(|branch| branch.last_failed_rebase.is_none()) {
+        for parent in branch.parents.iter().filter_map(|parent_id| dag.get_branch(parent_id)) {
+            match git.is_ancestor(&local_branch_ref(&parent.git_name), &local_branch_ref(&branch.git_name)) {
+                Ok(true) => {}
+                Ok(false) => problems.push(format!(
+                    "'{}' is recorded as a parent of '{}' but is not its git ancestor (run dagit update)",
+                    parent.git_name, branch.git_name,
+                )),
+                Err(e) => problems.push(format!(
+                    "Couldn't check whether '{}' is a git ancestor of '{}': {}",
+                    parent.git_name, branch.git_name, e,
+                )),
+            }
+        }
+    }
+    problems
+}
+
+fn handle_note_command(branch_name: &str, note: Option<String>) -> Result<(), CommandError> {
+    // Load existing DAG from file
+    let mut dag = load_dag()?;
+
+    let branch = dag.find_branch_by_name_mut(branch_name)
+        .ok_or_else(|| format!("Branch '{}' is not tracked", branch_name))?;
+    branch.note = note.clone();
+
+    // Save updated DAG back to file
+    save_dag(&dag)?;
+
+    match note {
+        Some(_) => println!("Updated the note on '{}'", branch_name),
+        None => println!("Cleared the note on '{}'", branch_name),
+    }
+    Ok(())
+}
+
+fn handle_set_remote_name_command(branch_name: &str, remote_name: Option<String>) -> Result<(), CommandError> {
+    let mut dag = load_dag()?;
+
+    let branch = dag.find_branch_by_name_mut(branch_name)
+        .ok_or_else(|| format!("Branch '{}' is not tracked", branch_name))?;
+    // The local name is the default, so there's nothing to store for it
+    branch.remote_name = remote_name.filter(|name| name != branch_name);
+    let remote_branch_name = branch.remote_branch_name().to_string();
+
+    save_dag(&dag)?;
+
+    println!("'{}' is pushed to origin as '{}'", branch_name, remote_branch_name);
+    Ok(())
+}
+
+fn handle_set_trunk_command(branch_name: Option<&str>) -> Result<(), CommandError> {
+    let mut dag = load_dag()?;
+
+    dag.trunk = branch_name.map(|name| find_tracked_branch(&dag, name)).transpose()?;
+    save_dag(&dag)?;
+
+    match branch_name {
+        Some(name) => println!("'{}' is now the trunk", name),
+        None => println!("The trunk is no longer pinned"),
+    }
+    Ok(())
+}
+
+fn handle_current_command(git: &dyn GitOps) -> Result<(), CommandError> {
+    let current_branch = git.get_current_git_branch()?;
+    let dag = load_dag()?;
+
+    let Some(branch) = dag.find_branch_by_name(&current_branch) else {
+        println!("Branch '{}' is not tracked. Run 'dagit track' to add it.", current_branch);
+        return Ok(());
+    };
+
+    let children: Vec<String> = branch.children.iter()
+        .filter_map(|id| dag.get_branch(id))
+        .map(|child| child.git_name.clone())
+        .collect();
+    let ahead_behind = ahead_behind_info(git, branch, &dag)
+        .map(|info| format!(" {}", info))
+        .unwrap_or_default();
+
+    println!("Branch:   {}", branch.git_name);
+    println!("Stack:    {}", primary_parent_chain(branch.uid, &dag).join(" → "));
+    println!("Children: {}", if children.is_empty() { "none".to_string() } else { children.join(", ") });
+    println!("PR:       {}", branch.pr_number.map(|pr| format!("#{}", pr)).unwrap_or_else(|| "none".to_string()));
+    println!("Status:   {}{}", branch_status(git, branch, &dag), ahead_behind);
+    Ok(())
+}
 
-    if dag.is_empty() {
-        println!("No branches are being tracked. Use 'dagit track' to add branches first.");
-        return;
-    }
+/// A move along the stack of the current branch, see handle_step_command
+#[derive(Clone, Copy)]
+enum StackStep {
+    /// To the branch's only child
+    Next,
+    /// To the branch's primary parent
+    Prev,
+}
 
-    // Get branches in topological sort order
-    let sorted_branch_ids = match dag.topological_sort() {
-        Ok(ids) => ids,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+/// Check out the branch one step up or down the current branch's stack
+/// Having nowhere to go is not an error, but having several children to choose from is
+fn handle_step_command(git: &dyn GitOps, step: StackStep) -> Result<(), CommandError> {
+    let current_branch = git.get_current_git_branch()?;
+    let dag = load_dag()?;
+
+    let Some(target) = step_target(&dag, &current_branch, step)? else {
+        match step {
+            StackStep::Next => println!("'{}' has no tracked children", current_branch),
+            StackStep::Prev => println!("'{}' has no tracked parent", current_branch),
         }
+        return Ok(());
     };
 
-    println!("Processing {} branches in topological order for PR creation...", sorted_branch_ids.len());
-
-    let mut pr_created_count = 0;
-    let mut pr_skipped_count = 0;
-    let mut pr_error_count = 0;
+    git.checkout_branch(&target).map_err(|e| format!("Failed to check out '{}': {}", target, e))?;
+    println!("Now on '{}'", target);
+    Ok(())
+}
 
-    // Process each branch in topological order
-    for &branch_id in &sorted_branch_ids {
-        // Get branch name for logging
-        let branch_name = dag.get_branch(&branch_id)
-            .map(|b| b.git_name.clone())
-            .unwrap_or_else(|| "unknown".to_string());
-
-        println!("*** Processing branch '{}' ***", branch_name);
-
-        // Create PR for this branch
-        match create_pr_for_branch(branch_id, &mut dag) {
-            Ok(Some(pr_number)) => {
-                println!("  ✓ Created PR #{}", pr_number);
-                pr_created_count += 1;
-            }
-            Ok(None) => {
-                println!("  - Skipped (already exists or no parent)");
-                pr_skipped_count += 1;
-            }
-            Err(e) => {
-                println!("  ✗ Error: {}", e);
-                pr_error_count += 1;
+/// The branch next/prev would check out from `branch_name`, or None if there is none
+fn step_target(dag: &dag::Dag, branch_name: &str, step: StackStep) -> Result<Option<String>, CommandError> {
+    let branch_id = find_tracked_branch(dag, branch_name)?;
+    match step {
+        StackStep::Prev => Ok(primary_parent_name(dag, branch_id)),
+        StackStep::Next => {
+            let children = dag.get_branch(&branch_id).map(|branch| names_in_order(dag, &branch.children)).unwrap_or_default();
+            match children.as_slice() {
+                [] => Ok(None),
+                [child] => Ok(Some(child.clone())),
+                _ => Err(format!("'{}' has several children, check out one of them: {}", branch_name, children.join(", ")).into()),
             }
         }
     }
+}
 
-    // Save updated DAG back to file (to persist pr_number updates)
-    match write_dag_to_file(&dag) {
-        Ok(()) => {},
-        Err(e) => {
-            eprintln!("Failed to write DAG file: {}", e);
-            std::process::exit(1);
+/// Names of the branch and its primary ancestors, from the root down to the branch itself
+fn primary_parent_chain(branch_id: dag::BranchId, dag: &dag::Dag) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = dag.get_branch(&branch_id);
+
+    // The seen set keeps a corrupted DAG with a cycle from looping forever
+    while let Some(branch) = current {
+        if !seen.insert(branch.uid) {
+            break;
         }
+        chain.push(branch.git_name.clone());
+        current = branch.parents.first().and_then(|parent_id| dag.get_branch(parent_id));
     }
 
-    // Summary
-    println!();
-    println!("Submit completed:");
-    println!("  ✓ {} PRs created", pr_created_count);
-    println!("  - {} PRs skipped (already exist or no parent)", pr_skipped_count);
-    println!("  ✗ {} PR creation errors", pr_error_count);
-
-    if pr_error_count > 0 {
-        println!();
-        println!("Some branches had PR creation errors. Check the output above for details.");
-    }
+    chain.reverse();
+    chain
 }
 
 const DAG_INDENT_ROWS: usize = 3;
 
-fn handle_dag_command() {
+/// Names of the given branches, keeping their order (e.g. primary parent first)
+fn names_in_order(dag: &dag::Dag, ids: &[dag::BranchId]) -> Vec<String> {
+    ids.iter()
+        .filter_map(|id| dag.get_branch(id))
+        .map(|branch| branch.git_name.clone())
+        .collect()
+}
+
+/// Options controlling what `dagit dag` prints
+struct DagOptions {
+    format: DagFormat,
+    /// Print only the direct parents of this branch
+    parents_of: Option<String>,
+    /// Print only the direct children of this branch
+    children_of: Option<String>,
+    /// Root the output at this branch, leaving out everything that isn't its descendant
+    since: Option<String>,
+    /// Prefix each branch in the tree with its internal id
+    show_ids: bool,
+    /// Deepest level of the tree that is printed; the branches below it are only counted
+    max_depth: Option<usize>,
+    /// Characters of each commit hash to show, 0 for the full hash
+    abbrev: usize,
+}
+
+fn handle_dag_command(git: &dyn GitOps, options: &DagOptions) -> Result<(), CommandError> {
     // Load existing DAG from file
-    let dag = match read_dag_from_file() {
-        Ok(dag) => dag,
-        Err(e) => {
-            eprintln!("Failed to read DAG file: {}", e);
-            std::process::exit(1);
+    let dag = load_dag()?;
+
+    // Hashes are 40 characters, or 64 in SHA-256 repositories
+    let hash_len = git.get_branch_commit("HEAD").map(|hash| hash.len()).unwrap_or(40);
+    if options.abbrev > hash_len {
+        return Err(format!("--abbrev must be at most {} (the length of a commit hash), or 0 for the full hash", hash_len).into());
+    }
+
+    // Plain name lists for scripting, without any tree formatting
+    if let Some(branch_name) = &options.parents_of {
+        let branch_id = find_tracked_branch(&dag, branch_name)?;
+        let parents = dag.get_branch(&branch_id).map(|b| b.parents.clone()).unwrap_or_default();
+        for name in names_in_order(&dag, &parents) {
+            println!("{}", name);
         }
-    };
+        return Ok(());
+    }
+    if let Some(branch_name) = &options.children_of {
+        let branch_id = find_tracked_branch(&dag, branch_name)?;
+        let children = dag.get_branch(&branch_id).map(|b| b.children.clone()).unwrap_or_default();
+        for name in names_in_order(&dag, &children) {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
 
     if dag.is_empty() {
         println!("No branches are being tracked. Use 'dagit track' to add branches first.");
-        return;
+        return Ok(());
     }
 
-    // Perform DFS traversal
-    print_dag(&dag);
+    if let Some(branch_name) = &options.since {
+        let branch_id = find_tracked_branch(&dag, branch_name)?;
+        let subtree = dag.get_recursive_children(branch_id);
+        match options.format {
+            DagFormat::Tree | DagFormat::Compact => dfs_print(git, &dag, branch_id, 0, 0, &mut HashSet::new(), options),
+            DagFormat::Dot => print!("{}", render_dot(git, &dag, Some(&subtree))),
+        }
+        return Ok(());
+    }
+
+    match options.format {
+        // Perform DFS traversal
+        DagFormat::Tree | DagFormat::Compact => print_dag(git, &dag, options),
+        DagFormat::Dot => print!("{}", render_dot(git, &dag, None)),
+    }
+    Ok(())
+}
+
+/// Render the DAG as a Graphviz digraph with one node per branch and parent -> child edges
+/// Nodes are colored by status: green when up to date, red when the last update failed
+/// With `only`, branches outside of the given set (and their edges) are left out
+fn render_dot(git: &dyn GitOps, dag: &dag::Dag, only: Option<&HashSet<dag::BranchId>>) -> String {
+    let included = |id: &dag::BranchId| only.is_none_or(|only| only.contains(id));
+    let mut branch_ids: Vec<_> = dag.branches.keys().filter(|id| included(id)).cloned().collect();
+    branch_ids.sort_by_key(|id| id.0);
+
+    let mut dot = String::from("digraph dagit {\n");
+    dot.push_str("    node [shape=box, style=filled, fillcolor=white];\n");
+
+    for branch_id in &branch_ids {
+        let branch = &dag.branches[branch_id];
+        let mut label = branch.git_name.clone();
+        if let Some(pr_number) = branch.pr_number {
+            label.push_str(&format!("\\nPR #{}", pr_number));
+        }
+
+        let color = match branch_status(git, branch, dag) {
+            BranchStatus::FailedUpdate => "lightcoral",
+            BranchStatus::UpToDate => "palegreen",
+            _ => "white",
+        };
+
+        dot.push_str(&format!("    b{} [label=\"{}\", fillcolor={}];\n", branch_id.0, escape_dot(&label), color));
+    }
+
+    let mut edges: Vec<_> = dag.edges().filter(|(parent, child)| included(parent) && included(child)).collect();
+    edges.sort_by_key(|(parent, child)| (parent.0, child.0));
+    for (parent_id, child_id) in edges {
+        dot.push_str(&format!("    b{} -> b{};\n", parent_id.0, child_id.0));
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Escape double quotes for a DOT string, leaving `\n` line breaks intact
+fn escape_dot(label: &str) -> String {
+    label.replace('"', "\\\"")
 }
 
-fn print_dag(dag: &dag::Dag) {
+fn print_dag(git: &dyn GitOps, dag: &dag::Dag, options: &DagOptions) {
     // Find root branches (branches with no parents)
     let mut roots = Vec::new();
     for (&branch_id, branch) in &dag.branches {
@@ -547,15 +2644,18 @@ fn print_dag(dag: &dag::Dag) {
 
     // DFS traversal from all roots
     for &root_id in &roots {
-        dfs_print(dag, root_id, 0, &mut visited);
+        dfs_print(git, dag, root_id, 0, 0, &mut visited, options);
     }
 }
 
 fn dfs_print(
+    git: &dyn GitOps,
     dag: &dag::Dag,
     branch_id: dag::BranchId,
     indent: usize,
+    depth: usize,
     visited: &mut std::collections::HashSet<dag::BranchId>,
+    options: &DagOptions,
 ) {
     if visited.contains(&branch_id) {
         return;
@@ -568,10 +2668,14 @@ fn dfs_print(
     };
 
     // Print the branch info
-    match get_branch_info(branch, 0, dag) {
+    let info = match options.format {
+        DagFormat::Compact => Ok(get_compact_branch_info(git, branch, dag, options.show_ids)),
+        _ => get_branch_info(git, branch, 0, dag, options.show_ids, options.abbrev),
+    };
+    match info {
         Ok(info) => {
             let prefix_str = if indent > 0 {
-                format!("{}", ("▼".to_owned()+&(" ".repeat(DAG_INDENT_ROWS))).repeat(indent))
+                ("▼".to_owned()+&(" ".repeat(DAG_INDENT_ROWS))).repeat(indent)
             } else {
                 "".to_string()
             };
@@ -581,9 +2685,19 @@ fn dfs_print(
     }
 
     // Get children and sort them for consistent output
-    let mut children: Vec<_> = branch.children.iter().cloned().collect();
+    let mut children: Vec<_> = branch.children.to_vec();
     children.sort_by_key(|&id| id.0);
 
+    // Past the depth limit, only say how much is left out
+    if !children.is_empty() && options.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        let hidden = dag.get_recursive_children(branch_id)
+            .into_iter()
+            .filter(|id| *id != branch_id && !visited.contains(id))
+            .count();
+        println!("{}└ ... ({} more)", " ".repeat(indent), hidden);
+        return;
+    }
+
     // Print children
     if !children.is_empty() {
         let continuation_line_char = if children.len() < 2 { "│" } else { "├" };
@@ -591,7 +2705,7 @@ fn dfs_print(
         println!("{}│{}", " ".repeat(indent), (" ".repeat(DAG_INDENT_ROWS) + "▼").repeat(children.len().saturating_sub(1)));
     }
     for (i, &child_id) in children.iter().enumerate() {
-        dfs_print(dag, child_id, indent + children.len() - i - 1, visited);
+        dfs_print(git, dag, child_id, indent + children.len() - i - 1, depth + 1, visited, options);
     }
 }
 
@@ -599,22 +2713,708 @@ fn dfs_print(
 mod tests {
     use super::*;
     use crate::dag::{Branch, BranchId, Dag};
+    use crate::mock_git::MockGit;
 
     fn create_test_branch(id: usize, name: String, parents: Vec<BranchId>, pr_number: Option<usize>, last_failed_rebase: Option<String>) -> Branch {
-        let mut branch = Branch::with_id(BranchId(id), name);
+        let mut branch = Branch::new(BranchId(id), name);
         branch.parents = parents;
         branch.pr_number = pr_number;
-        branch.last_failed_rebase = last_failed_rebase;
+        branch.last_failed_rebase = last_failed_rebase.map(|target| dag::FailedRebase::new(target, ""));
         branch
     }
 
+    /// Run git in `dir`, panicking if it fails
+    fn git_in(dir: &std::path::Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("Failed to run git");
+        assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+
+    /// A dag with main <- feature, where main and feature are only known by name
+    fn dag_with_feature_on_main(main_name: &str, feature_name: &str) -> (Dag, BranchId) {
+        let mut dag = Dag::new();
+        let main = dag.create_branch(main_name.to_string());
+        let feature = dag.create_branch(feature_name.to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        (dag, feature)
+    }
+
+    #[test]
+    fn test_branch_status_with_mock_git() {
+        let (dag, feature) = dag_with_feature_on_main("main", "feature");
+        let feature = dag.get_branch(&feature).unwrap();
+
+        let git = MockGit::with_branches(&["main", "feature"]).with_ancestor("main", "feature");
+        assert_eq!(branch_status(&git, feature, &dag), BranchStatus::UpToDate);
+
+        let git = MockGit::with_branches(&["main", "feature"]);
+        assert_eq!(branch_status(&git, feature, &dag), BranchStatus::OutOfDate);
+    }
+
+    /// Run update_branch on each branch in turn, returning the failed and skipped branches
+    fn update_with(git: &MockGit, dag: &mut Dag, branch_ids: &[BranchId]) -> (HashSet<BranchId>, HashSet<BranchId>) {
+        update_with_options(git, dag, branch_ids, &UpdateOptions::default())
+    }
+
+    fn update_with_options(git: &MockGit, dag: &mut Dag, branch_ids: &[BranchId], options: &UpdateOptions) -> (HashSet<BranchId>, HashSet<BranchId>) {
+        let mut progress = UpdateProgress::default();
+        for (index, &branch_id) in branch_ids.iter().enumerate() {
+            update_branch(git, dag, branch_id, (index + 1, branch_ids.len()), options, &mut progress);
+        }
+        (progress.failed, progress.skipped)
+    }
+
+    #[test]
+    fn test_update_branch_rebases_onto_parent() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let git = MockGit::with_branches(&["main", "feature"]).with_origin_branches(&["main", "feature"]);
+
+        let (failed, skipped) = update_with(&git, &mut dag, &[feature]);
+
+        assert!(failed.is_empty() && skipped.is_empty());
+        assert_eq!(git.calls(), vec!["rebase_branch feature main"]);
+    }
+
+    #[test]
+    fn test_unverified_edges_reports_parents_missing_from_updated_branches() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let fix = dag.create_branch("fix".to_string());
+        dag.add_parent_child_relationship_by_id(fix, feature).unwrap();
+        let git = MockGit::with_branches(&["main", "feature", "fix"]).with_ancestor("main", "feature");
+
+        let updated = HashSet::from([feature, fix]);
+        assert_eq!(unverified_edges(&git, &dag, &updated), vec!["'fix' does not contain its parent 'feature'"]);
+        // Branches that weren't updated aren't checked
+        assert!(unverified_edges(&git, &dag, &HashSet::from([feature])).is_empty());
+
+        // Nor are parents other than the primary one, which update doesn't rebase onto
+        let other = dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship_by_id(feature, other).unwrap();
+        assert!(unverified_edges(&git, &dag, &HashSet::from([feature])).is_empty());
+    }
+
+    #[test]
+    fn test_update_branch_with_several_parents_uses_primary_parent() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let other = dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship_by_id(feature, other).unwrap();
+        let git = MockGit::with_branches(&["main", "other", "feature"]);
+
+        let (failed, _) = update_with(&git, &mut dag, &[feature]);
+        assert!(failed.is_empty());
+        assert_eq!(git.calls(), vec!["rebase_branch feature main"]);
+
+        dag.set_primary_parent(feature, other).unwrap();
+        let git = MockGit::with_branches(&["main", "other", "feature"]);
+        update_with(&git, &mut dag, &[feature]);
+        assert_eq!(git.calls(), vec!["rebase_branch feature other"]);
+    }
+
+    #[test]
+    fn test_current_stack_leaves_out_sibling_stacks() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let child = dag.create_branch("child".to_string());
+        let sibling = dag.create_branch("sibling".to_string());
+        dag.add_parent_child_relationship_by_id(child, feature).unwrap();
+        dag.add_parent_child_relationship("sibling", "main").unwrap();
+        let main = dag.find_branch_by_name("main").unwrap().uid;
+
+        let git = MockGit::with_branches(&["feature", "main", "child", "sibling"]);
+        assert_eq!(current_stack(&git, &dag).unwrap(), HashSet::from([main, feature, child]));
+        assert!(!current_stack(&git, &dag).unwrap().contains(&sibling));
+
+        let git = MockGit::with_branches(&["untracked"]);
+        assert!(current_stack(&git, &dag).unwrap_err().message.contains("'untracked' is not tracked"));
+    }
+
+    #[test]
+    fn test_update_branch_skips_children_of_failed_branch() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let child = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(child, feature).unwrap();
+        let git = MockGit::with_branches(&["main", "feature", "child"]).with_conflict("feature");
+
+        let (failed, skipped) = update_with(&git, &mut dag, &[feature, child]);
+
+        assert_eq!(failed, HashSet::from([feature]));
+        assert_eq!(skipped, HashSet::from([child]));
+        assert_eq!(dag.get_branch(&feature).unwrap().last_failed_rebase, Some(dag::FailedRebase::new("main", "main-tip")));
+        assert_eq!(git.calls(), vec!["rebase_branch feature main"]);
+    }
+
+    #[test]
+    fn test_update_branch_leaves_open_prs_alone() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        dag.get_branch_mut(&feature).unwrap().pr_number = Some(7);
+        let git = MockGit::with_branches(&["main", "feature"]).with_open_pr(7);
+
+        let (_, skipped) = update_with(&git, &mut dag, &[feature]);
+
+        assert_eq!(skipped, HashSet::from([feature]));
+        assert!(git.calls().is_empty());
+    }
+
+    #[test]
+    fn test_count_open_prs_checks_each_recorded_pr() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let other = dag.create_branch("other".to_string());
+        dag.get_branch_mut(&feature).unwrap().pr_number = Some(7);
+        dag.get_branch_mut(&other).unwrap().pr_number = Some(8);
+
+        let git = MockGit::with_branches(&["main", "feature", "other"]).with_open_pr(7);
+        assert_eq!(count_open_prs(&git, &dag), (1, vec![]));
+
+        let git = MockGit::with_branches(&["main", "feature", "other"]).with_pr_state_error("gh: command not found");
+        assert_eq!(count_open_prs(&git, &dag), (0, vec!["gh: command not found".to_string(); 2]));
+    }
+
+    #[test]
+    fn test_update_branch_pulls_trunk_under_its_remote_name() {
+        let (mut dag, _) = dag_with_feature_on_main("main", "feature");
+        let main = dag.find_branch_by_name("main").unwrap().uid;
+        dag.get_branch_mut(&main).unwrap().remote_name = Some("trunk".to_string());
+        let git = MockGit::with_branches(&["main", "feature"]).with_origin_branches(&["trunk"]);
+        let options = UpdateOptions { trunk: Some("main".to_string()), ..Default::default() };
+
+        let (failed, skipped) = update_with_options(&git, &mut dag, &[main], &options);
+
+        assert!(failed.is_empty() && skipped.is_empty());
+        assert_eq!(git.calls(), vec!["pull_rebase_ff main origin trunk"]);
+    }
+
+    #[test]
+    fn test_update_branch_rebases_branch_whose_pr_is_merged() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        dag.get_branch_mut(&feature).unwrap().pr_number = Some(7);
+        let git = MockGit::with_branches(&["main", "feature"]);
+
+        let (failed, skipped) = update_with(&git, &mut dag, &[feature]);
+
+        assert!(failed.is_empty() && skipped.is_empty());
+        assert_eq!(git.calls(), vec!["rebase_branch feature main"]);
+    }
+
+    #[test]
+    fn test_update_branch_skips_and_reports_pr_that_cant_be_checked() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let other = dag.create_branch("other".to_string());
+        let main = dag.find_branch_by_name("main").unwrap().uid;
+        dag.add_parent_child_relationship_by_id(other, main).unwrap();
+        dag.get_branch_mut(&feature).unwrap().pr_number = Some(7);
+        dag.get_branch_mut(&other).unwrap().pr_number = Some(8);
+        let git = MockGit::with_branches(&["main", "feature", "other"]).with_pr_state_error("gh: command not found");
+
+        let mut progress = UpdateProgress::default();
+        for (index, branch_id) in [feature, other].into_iter().enumerate() {
+            update_branch(&git, &mut dag, branch_id, (index + 1, 2), &UpdateOptions::default(), &mut progress);
+        }
+
+        assert!(progress.failed.is_empty());
+        assert_eq!(progress.skipped, HashSet::from([feature, other]));
+        assert_eq!(progress.pr_check_errors, vec!["gh: command not found"; 2]);
+        assert!(git.calls().is_empty());
+
+        // --allow-pr-rebase doesn't look the PRs up at all
+        let options = UpdateOptions { allow_pr_rebase: true, ..Default::default() };
+        let (failed, skipped) = update_with_options(&git, &mut dag, &[feature], &options);
+        assert!(failed.is_empty() && skipped.is_empty());
+        assert_eq!(git.calls(), vec!["rebase_branch feature main"]);
+    }
+
+    #[test]
+    fn test_update_branch_removes_redundant_branch() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let child = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(child, feature).unwrap();
+        // feature has nothing left on top of main
+        let git = MockGit::with_branches(&["main", "feature", "child"]).with_ancestor("feature", "main");
+
+        let (_, skipped) = update_with(&git, &mut dag, &[feature]);
+
+        assert_eq!(skipped, HashSet::from([feature]));
+        assert!(dag.get_branch(&feature).is_none());
+        assert_eq!(primary_parent_name(&dag, child).as_deref(), Some("main"));
+        assert_eq!(git.calls(), vec!["rebase_branch feature main", "update_pr_target_for_branch child main"]);
+    }
+
+    #[test]
+    fn test_update_branch_closes_pr_of_redundant_branch_only_when_asked() {
+        for close_merged_prs in [false, true] {
+            let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+            dag.get_branch_mut(&feature).unwrap().pr_number = Some(7);
+            let git = MockGit::with_branches(&["main", "feature"]).with_open_pr(7).with_ancestor("feature", "main");
+            // Without --allow-pr-rebase, so the open PR mustn't keep the branch from being removed
+            let options = UpdateOptions { close_merged_prs, ..Default::default() };
+
+            update_with_options(&git, &mut dag, &[feature], &options);
+
+            assert!(dag.get_branch(&feature).is_none());
+            assert_eq!(git.calls().contains(&"close_pr 7".to_string()), close_merged_prs);
+        }
+    }
+
+    #[test]
+    fn test_submit_waves_group_independent_branches() {
+        // main -> a -> d, and main -> b, c
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let a = dag.create_branch("a".to_string());
+        let b = dag.create_branch("b".to_string());
+        let c = dag.create_branch("c".to_string());
+        let d = dag.create_branch("d".to_string());
+        for (child, parent) in [(a, main), (b, main), (c, main), (d, a)] {
+            dag.add_parent_child_relationship_by_id(child, parent).unwrap();
+        }
+        let order = vec![main, a, d, b, c];
+
+        assert_eq!(submit_waves(&dag, &order, 1), vec![vec![main], vec![a], vec![d], vec![b], vec![c]]);
+        assert_eq!(submit_waves(&dag, &order, 2), vec![vec![main], vec![a, b], vec![c], vec![d]]);
+        assert_eq!(submit_waves(&dag, &order, 8), vec![vec![main], vec![a, b, c], vec![d]]);
+    }
+
+    #[test]
+    fn test_create_prs_concurrently_with_mock_git() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let siblings: Vec<BranchId> = ["a", "b", "c"].iter().map(|name| dag.create_branch(name.to_string())).collect();
+        for &sibling in &siblings {
+            dag.add_parent_child_relationship_by_id(sibling, main).unwrap();
+        }
+        let git = MockGit::with_branches(&["main", "a", "b", "c"]);
+        let options = SubmitOptions { flat: false, dry_run: false, push: true, concurrency: 3, metadata: PrMetadata::default() };
+
+        assert_eq!(create_prs_concurrently(&git, &dag, &[main], None, &options), vec![Ok(None)]);
+
+        let mut pr_numbers: Vec<usize> = create_prs_concurrently(&git, &dag, &siblings, None, &options)
+            .into_iter()
+            .map(|result| result.unwrap().unwrap())
+            .collect();
+        pr_numbers.sort();
+        assert_eq!(pr_numbers, vec![1, 2, 3]);
+
+        // The PRs were created on copies; recording them is up to the caller
+        assert!(siblings.iter().all(|id| dag.get_branch(id).unwrap().pr_number.is_none()));
+    }
+
+    #[test]
+    fn test_update_plan_follows_parents_that_move() {
+        // main -> feature -> fix, and main -> docs, with only feature behind its parent
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        dag.trunk = dag.find_branch_by_name("main").map(|main| main.uid);
+        let fix = dag.create_branch("fix".to_string());
+        dag.add_parent_child_relationship_by_id(fix, feature).unwrap();
+        dag.create_branch("docs".to_string());
+        dag.add_parent_child_relationship("docs", "main").unwrap();
+        dag.get_branch_mut(&fix).unwrap().pr_number = Some(7);
+        let git = MockGit::with_branches(&["main", "feature", "fix", "docs"])
+            .with_origin_branches(&["main", "feature"])
+            .with_ancestor("feature", "fix")
+            .with_ancestor("main", "docs");
+
+        assert_eq!(update_plan(&git, &dag).unwrap(), vec![
+            "main: fast-forward to origin/main",
+            "feature: rebase against origin/feature, rebase onto 'main' after it is updated (skipped if that fails)",
+            "docs: rebase onto 'main' after it is updated (skipped if that fails)",
+            "fix: skipped if PR #7 is open (unless --allow-pr-rebase), rebase onto 'feature' after it is updated (skipped if that fails)",
+        ]);
+        assert!(git.calls().is_empty(), "plan must not change anything");
+
+        // With main already up to date, only feature and the stack above it move
+        let git = git.with_ancestor("refs/remotes/origin/main", "main");
+        assert_eq!(update_plan(&git, &dag).unwrap()[..3], [
+            "main: trunk, up to date with origin/main",
+            "feature: rebase against origin/feature, rebase onto 'main'",
+            "docs: already on top of 'main'",
+        ]);
+    }
+
+    #[test]
+    fn test_same_tip_problems_groups_branches_by_commit() {
+        let (mut dag, _) = dag_with_feature_on_main("main", "feature");
+        dag.create_branch("renamed".to_string());
+        dag.create_branch("gone".to_string());
+        let git = MockGit::with_branches(&["main", "feature", "renamed"])
+            .with_commit("renamed", "abc1234def")
+            .with_commit("feature", "abc1234def");
+
+        assert_eq!(same_tip_problems(&git, &dag), vec![
+            "'feature', 'renamed' point at the same commit abc1234 and may be duplicates (combine them with dagit merge-nodes)".to_string(),
+        ]);
+
+        let git = MockGit::with_branches(&["main", "feature", "renamed"]);
+        assert!(same_tip_problems(&git, &dag).is_empty());
+    }
+
+    #[test]
+    fn test_ancestry_problems_lists_edges_git_disagrees_with() {
+        let (mut dag, feature) = dag_with_feature_on_main("main", "feature");
+        let child = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(child, feature).unwrap();
+        let git = MockGit::with_branches(&["main", "feature", "child"]).with_ancestor("main", "feature");
+
+        assert_eq!(ancestry_problems(&git, &dag), vec![
+            "'feature' is recorded as a parent of 'child' but is not its git ancestor (run dagit update)".to_string(),
+        ]);
+
+        // A failed update already explains the drift
+        dag.get_branch_mut(&child).unwrap().last_failed_rebase = Some(dag::FailedRebase::new("feature", ""));
+        assert!(ancestry_problems(&git, &dag).is_empty());
+    }
+
+    #[test]
+    fn test_root_warnings_skip_trunk_excluded_and_local_only_roots() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let feature = dag.create_branch("feature".to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        dag.create_branch("release".to_string());
+        let experiment = dag.create_branch("experiment".to_string());
+        dag.create_branch("scratch".to_string());
+        let git = MockGit::with_branches(&["main", "feature", "release", "experiment", "scratch"])
+            .with_origin_branches(&["main", "feature", "release", "experiment"]);
+
+        let warnings = root_warnings(&git, &dag, Some("main"), &HashSet::from([experiment]));
+
+        assert_eq!(warnings, vec![
+            "branch 'release' is a root and will be rebased against origin/release — is this intended? (silence with --no-root-warnings)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_track_branch_only_on_origin_needs_from_origin() {
+        let git = MockGit::with_branches(&["main"]).with_origin_branches(&["main", "remote-only"]);
+        let mut dag = Dag::new();
+
+        let error = track_branch(&git, &mut dag, "remote-only", &TrackOptions::default()).unwrap_err();
+        assert!(error.message.contains("does not exist locally"), "{}", error.message);
+        assert!(git.calls().is_empty() && dag.is_empty());
+
+        let options = TrackOptions { from_origin: true, ..Default::default() };
+        track_branch(&git, &mut dag, "remote-only", &options).unwrap();
+        assert_eq!(git.calls(), vec!["create_branch_from_origin remote-only"]);
+        assert!(dag.find_branch_by_name("remote-only").is_some());
+    }
+
+    #[test]
+    fn test_untracked_ancestor_chain_stops_at_tracked_branch() {
+        let git = MockGit::with_branches(&["main", "base", "feature", "child"])
+            .with_closest_parent("base", "main")
+            .with_closest_parent("feature", "base")
+            .with_closest_parent("child", "feature");
+
+        let mut dag = Dag::new();
+        assert_eq!(untracked_ancestor_chain(&git, &dag, "child", None).unwrap(), vec!["child", "feature", "base", "main"]);
+
+        dag.create_branch("base".to_string());
+        assert_eq!(untracked_ancestor_chain(&git, &dag, "child", None).unwrap(), vec!["child", "feature"]);
+
+        // A branch without a detected parent is a chain of its own
+        assert_eq!(untracked_ancestor_chain(&git, &Dag::new(), "main", None).unwrap(), vec!["main"]);
+    }
+
+    #[test]
+    fn test_step_target() {
+        let (mut dag, _) = dag_with_feature_on_main("main", "feature");
+
+        assert_eq!(step_target(&dag, "main", StackStep::Next).unwrap().as_deref(), Some("feature"));
+        assert_eq!(step_target(&dag, "feature", StackStep::Prev).unwrap().as_deref(), Some("main"));
+        assert_eq!(step_target(&dag, "feature", StackStep::Next).unwrap(), None);
+        assert_eq!(step_target(&dag, "main", StackStep::Prev).unwrap(), None);
+
+        dag.create_branch("other".to_string());
+        dag.add_parent_child_relationship("other", "main").unwrap();
+        let error = step_target(&dag, "main", StackStep::Next).unwrap_err();
+        assert!(error.message.contains("several children") && error.message.contains("feature, other"), "{}", error.message);
+
+        assert!(step_target(&dag, "untracked", StackStep::Next).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_dag_from_detected_parents() {
+        let git = MockGit::with_branches(&["main", "feature", "child", "other"])
+            .with_closest_parent("feature", "main")
+            .with_closest_parent("child", "feature")
+            .with_closest_parent("other", "main");
+        let names: Vec<String> = ["child", "main", "other", "feature", "main"].iter().map(|s| s.to_string()).collect();
+
+        let dag = reconstruct_dag(&git, &names).unwrap();
+
+        assert_eq!(dag.len(), 4);
+        let parent_of = |name: &str| primary_parent_name(&dag, dag.find_branch_by_name(name).unwrap().uid);
+        assert_eq!(parent_of("main"), None);
+        assert_eq!(parent_of("feature").as_deref(), Some("main"));
+        assert_eq!(parent_of("child").as_deref(), Some("feature"));
+        assert_eq!(parent_of("other").as_deref(), Some("main"));
+        assert!(dag.validate().is_empty());
+
+        // Same branches, same DAG
+        assert_eq!(reconstruct_dag(&git, &names).unwrap(), dag);
+    }
+
+    #[test]
+    fn test_branch_status_without_git() {
+        let root = create_test_branch(1, "main".to_string(), vec![], None, None);
+        assert_eq!(branch_status(&RealGit, &root, &Dag::new()), BranchStatus::NoParent);
+
+        // A failed update is reported even for a root branch
+        let failed = create_test_branch(1, "main".to_string(), vec![], None, Some("origin/main".to_string()));
+        assert_eq!(branch_status(&RealGit, &failed, &Dag::new()), BranchStatus::FailedUpdate);
+
+        // Branches that don't exist in git can't be checked
+        let (dag, feature) = dag_with_feature_on_main("dagit-test-missing-main", "dagit-test-missing-feature");
+        assert_eq!(branch_status(&RealGit, dag.get_branch(&feature).unwrap(), &dag), BranchStatus::Unknown);
+
+        assert_eq!(BranchStatus::UpToDate.to_string(), "✅ up to date");
+        assert_eq!(BranchStatus::NoParent.glyph(), "⚪");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_branch_status_with_git() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path();
+        git_in(path, &["init", "-b", "main"]);
+        git_in(path, &["config", "user.name", "Test User"]);
+        git_in(path, &["config", "user.email", "test@example.com"]);
+        git_in(path, &["commit", "--allow-empty", "-m", "Initial commit"]);
+        git_in(path, &["checkout", "-b", "feature"]);
+        git_in(path, &["commit", "--allow-empty", "-m", "Feature work"]);
+
+        let original_dir = std::env::current_dir().expect("Failed to get current dir");
+        std::env::set_current_dir(path).expect("Failed to change to temp dir");
+
+        let (dag, feature) = dag_with_feature_on_main("main", "feature");
+        let up_to_date = branch_status(&RealGit, dag.get_branch(&feature).unwrap(), &dag);
+        git_in(path, &["checkout", "main"]);
+        git_in(path, &["commit", "--allow-empty", "-m", "More main work"]);
+        let out_of_date = branch_status(&RealGit, dag.get_branch(&feature).unwrap(), &dag);
+
+        std::env::set_current_dir(&original_dir).expect("Failed to restore directory");
+
+        assert_eq!(up_to_date, BranchStatus::UpToDate);
+        assert_eq!(out_of_date, BranchStatus::OutOfDate);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_track_in_empty_repo() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path();
+        git_in(path, &["init", "-b", "main"]);
+
+        let original_dir = std::env::current_dir().expect("Failed to get current dir");
+        std::env::set_current_dir(path).expect("Failed to change to temp dir");
+
+        let result = handle_track_command(&RealGit, None, &TrackOptions::default());
+
+        std::env::set_current_dir(&original_dir).expect("Failed to restore directory");
+
+        assert_eq!(result.unwrap_err().message, "repository has no commits yet");
+        assert!(!path.join(".dagit").exists());
+    }
+
+    #[test]
+    fn test_format_error_json() {
+        let error = CommandError {
+            message: "1 branches failed to update".to_string(),
+            failed_branches: vec!["feature".to_string()],
+            skipped_branches: vec!["feature-child".to_string()],
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&format_error_json("update", &error)).unwrap();
+        assert_eq!(json["error"], "1 branches failed to update");
+        assert_eq!(json["command"], "update");
+        assert_eq!(json["failed_branches"], serde_json::json!(["feature"]));
+        assert_eq!(json["skipped_branches"], serde_json::json!(["feature-child"]));
+
+        // Plain errors have empty branch lists
+        let json: serde_json::Value = serde_json::from_str(&format_error_json("track", &"oops".into())).unwrap();
+        assert_eq!(json["failed_branches"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_should_colorize() {
+        // Auto follows NO_COLOR and the terminal
+        assert!(should_colorize(ColorMode::Auto, false, false, true));
+        assert!(!should_colorize(ColorMode::Auto, false, false, false));
+        assert!(!should_colorize(ColorMode::Auto, false, true, true));
+
+        // Explicit modes ignore the environment
+        assert!(should_colorize(ColorMode::Always, false, true, false));
+        assert!(!should_colorize(ColorMode::Never, false, false, true));
+
+        // --no-color beats everything
+        assert!(!should_colorize(ColorMode::Always, true, false, true));
+    }
+
+    #[test]
+    fn test_get_branch_info_with_note() {
+        let mut branch = create_test_branch(1, "feature".to_string(), vec![], Some(3), None);
+        branch.note = Some("refactor auth".to_string());
+        let dag = Dag::new();
+
+        let output = get_branch_info(&RealGit, &branch, 0, &dag, false, DEFAULT_HASH_ABBREV).unwrap();
+        assert!(output.contains("refactor auth"));
+        // The note comes last, after the PR number
+        assert!(output.find("PR #3").unwrap() < output.find("refactor auth").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_name() {
+        assert_eq!(truncate_name("feature", 24), "feature");
+        assert_eq!(truncate_name("feature", 7), "feature");
+        assert_eq!(truncate_name("feature-login", 8), "feature…");
+        assert_eq!(truncate_name("fix-ünïcode", 6), "fix-ü…");
+    }
+
+    #[test]
+    fn test_get_compact_branch_info() {
+        let branch = create_test_branch(1, "a-very-long-feature-branch-name".to_string(), vec![], Some(3), None);
+        let dag = Dag::new();
+
+        let output = get_compact_branch_info(&RealGit, &branch, &dag, false);
+        assert!(output.contains("a-very-long-feature-bra…"));
+        assert!(!output.contains("PR #3"));
+        assert!(!output.contains('|'));
+    }
+
+    #[test]
+    fn test_get_branch_info_with_ids() {
+        let branch = create_test_branch(3, "feature".to_string(), vec![], None, None);
+        let dag = Dag::new();
+
+        assert!(get_branch_info(&RealGit, &branch, 0, &dag, true, DEFAULT_HASH_ABBREV).unwrap().contains("[3] "));
+        assert!(!get_branch_info(&RealGit, &branch, 0, &dag, false, DEFAULT_HASH_ABBREV).unwrap().contains("[3]"));
+    }
+
+    #[test]
+    fn test_get_branch_info_shows_failed_rebase_commit() {
+        let mut branch = create_test_branch(1, "feature".to_string(), vec![], None, None);
+        branch.last_failed_rebase = Some(dag::FailedRebase::new("main", "abc1234def5678"));
+        let dag = Dag::new();
+
+        let output = get_branch_info(&MockGit::with_branches(&["feature"]), &branch, 0, &dag, false, DEFAULT_HASH_ABBREV).unwrap();
+        assert!(output.contains("failed update onto main@abc1234|"), "{}", output);
+
+        // Failures recorded before the commit was kept only show the target
+        assert_eq!(describe_failed_rebase(&dag::FailedRebase::new("main", ""), DEFAULT_HASH_ABBREV), "main");
+        assert_eq!(describe_failed_rebase(&dag::FailedRebase::new("main", "abc1234def"), 0), "main@abc1234def");
+    }
+
+    #[test]
+    fn test_get_branch_info_abbrev() {
+        let git = MockGit::with_branches(&["feature"]);
+        let branch = create_test_branch(1, "feature".to_string(), vec![], None, None);
+        let dag = Dag::new();
+
+        let short = get_branch_info(&git, &branch, 0, &dag, false, 4).unwrap();
+        assert!(short.contains("feat") && !short.contains("feature-tip"));
+        // 0 shows the whole hash
+        assert!(get_branch_info(&git, &branch, 0, &dag, false, 0).unwrap().contains("feature-tip"));
+    }
+
+    #[test]
+    fn test_primary_parent_chain() {
+        let mut dag = dag::Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let other_id = dag.create_branch("other".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+        let child_id = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(feature_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(feature_id, other_id).unwrap();
+        dag.add_parent_child_relationship_by_id(child_id, feature_id).unwrap();
+
+        // Only primary parents are followed
+        assert_eq!(primary_parent_chain(child_id, &dag), vec!["main", "feature", "child"]);
+        assert_eq!(primary_parent_chain(main_id, &dag), vec!["main"]);
+    }
+
+    #[test]
+    fn test_linear_stack_below() {
+        let mut dag = dag::Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let a_id = dag.create_branch("a".to_string());
+        let b_id = dag.create_branch("b".to_string());
+        dag.add_parent_child_relationship_by_id(a_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(b_id, a_id).unwrap();
+
+        assert_eq!(linear_stack_below(&dag, main_id), Some(vec![a_id, b_id]));
+        // Only roots start a stack
+        assert_eq!(linear_stack_below(&dag, a_id), None);
+
+        // A fork makes the stack non-linear
+        let c_id = dag.create_branch("c".to_string());
+        dag.add_parent_child_relationship_by_id(c_id, a_id).unwrap();
+        assert_eq!(linear_stack_below(&dag, main_id), None);
+    }
+
+    #[test]
+    fn test_format_ahead_behind() {
+        assert_eq!(format_ahead_behind(3, 1), "↑3 ↓1");
+        assert_eq!(format_ahead_behind(0, 0), "↑0 ↓0");
+    }
+
+    #[test]
+    fn test_ahead_behind_info_root_branch() {
+        let mut dag = Dag::new();
+        let branch = create_test_branch(1, "main".to_string(), vec![], None, None);
+        dag.insert_branch(branch.clone()).unwrap();
+
+        assert_eq!(ahead_behind_info(&RealGit, &branch, &dag), None);
+    }
+
+    #[test]
+    fn test_render_dot() {
+        let mut dag = Dag::new();
+        dag.insert_branch(create_test_branch(1, "main".to_string(), vec![], None, None)).unwrap();
+        dag.insert_branch(create_test_branch(2, "feature".to_string(), vec![BranchId(1)], Some(12), Some("main".to_string()))).unwrap();
+        dag.get_branch_mut(&BranchId(1)).unwrap().children.push(BranchId(2));
+
+        let dot = render_dot(&RealGit, &dag, None);
+        assert!(dot.starts_with("digraph dagit {"));
+        assert!(dot.contains("b1 [label=\"main\", fillcolor=white];"));
+        assert!(dot.contains("b2 [label=\"feature\\nPR #12\", fillcolor=lightcoral];"));
+        assert!(dot.contains("b1 -> b2;"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_render_dot_subtree() {
+        let mut dag = Dag::new();
+        let main_id = dag.create_branch("main".to_string());
+        let feature_id = dag.create_branch("feature".to_string());
+        let child_id = dag.create_branch("child".to_string());
+        dag.add_parent_child_relationship_by_id(feature_id, main_id).unwrap();
+        dag.add_parent_child_relationship_by_id(child_id, feature_id).unwrap();
+
+        let subtree = dag.get_recursive_children(feature_id);
+        let dot = render_dot(&RealGit, &dag, Some(&subtree));
+        assert!(!dot.contains("label=\"main\""));
+        assert!(!dot.contains("b1 -> b2;"));
+        assert!(dot.contains("label=\"feature\""));
+        assert!(dot.contains("b2 -> b3;"));
+    }
+
+    #[test]
+    fn test_format_command_line_quotes_when_needed() {
+        let args: Vec<String> = ["pr", "create", "--title", "feature -> main", "--body", ""]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(format_command_line("gh", &args), "gh pr create --title 'feature -> main' --body ''");
+
+        let args = vec!["it's".to_string()];
+        assert_eq!(format_command_line("gh", &args), "gh 'it'\\''s'");
+    }
+
     #[test]
     fn test_get_branch_info_basic_formatting() {
         let mut dag = Dag::new();
         let branch = create_test_branch(1, "test-branch".to_string(), vec![], None, None);
-        dag.insert_branch(branch.clone());
+        dag.insert_branch(branch.clone()).unwrap();
 
-        let result = get_branch_info(&branch, 0, &dag);
+        let result = get_branch_info(&RealGit, &branch, 0, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result (may be Ok or Err depending on git state)
         assert!(result.is_ok() || result.is_err());
@@ -623,7 +3423,7 @@ mod tests {
         if let Ok(output) = result {
             assert!(output.contains("test-branch"));
             assert!(output.contains("|test-branch|"));
-            assert!(output.contains("🔄 out of date")); // No parents so out of date
+            assert!(output.contains("⚪ no parent"));
             assert!(!output.contains("PR"));
         }
     }
@@ -632,9 +3432,9 @@ mod tests {
     fn test_get_branch_info_with_indent() {
         let mut dag = Dag::new();
         let branch = create_test_branch(1, "feature".to_string(), vec![], None, None);
-        dag.insert_branch(branch.clone());
+        dag.insert_branch(branch.clone()).unwrap();
 
-        let result = get_branch_info(&branch, 2, &dag);
+        let result = get_branch_info(&RealGit, &branch, 2, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result
         assert!(result.is_ok() || result.is_err());
@@ -652,9 +3452,9 @@ mod tests {
     fn test_get_branch_info_with_pr_number() {
         let mut dag = Dag::new();
         let branch = create_test_branch(1, "feature".to_string(), vec![], Some(123), None);
-        dag.insert_branch(branch.clone());
+        dag.insert_branch(branch.clone()).unwrap();
 
-        let result = get_branch_info(&branch, 0, &dag);
+        let result = get_branch_info(&RealGit, &branch, 0, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result
         assert!(result.is_ok() || result.is_err());
@@ -670,9 +3470,9 @@ mod tests {
     fn test_get_branch_info_failed_update() {
         let mut dag = Dag::new();
         let branch = create_test_branch(1, "feature".to_string(), vec![], None, Some("origin/feature".to_string()));
-        dag.insert_branch(branch.clone());
+        dag.insert_branch(branch.clone()).unwrap();
 
-        let result = get_branch_info(&branch, 0, &dag);
+        let result = get_branch_info(&RealGit, &branch, 0, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result
         assert!(result.is_ok() || result.is_err());
@@ -690,14 +3490,14 @@ mod tests {
 
         // Create parent branch
         let parent_branch = create_test_branch(1, "main".to_string(), vec![], None, None);
-        dag.insert_branch(parent_branch);
+        dag.insert_branch(parent_branch).unwrap();
 
         // Create child branch with parent
         let child_branch = create_test_branch(2, "feature".to_string(), vec![BranchId(1)], None, None);
-        dag.insert_branch(child_branch.clone());
+        dag.insert_branch(child_branch.clone()).unwrap();
 
         // Note: The actual status depends on is_ancestor check which may fail in test environment
-        let result = get_branch_info(&child_branch, 0, &dag);
+        let result = get_branch_info(&RealGit, &child_branch, 0, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result
         assert!(result.is_ok() || result.is_err());
@@ -713,9 +3513,9 @@ mod tests {
     fn test_get_branch_info_formatting() {
         let mut dag = Dag::new();
         let branch = create_test_branch(1, "test-branch".to_string(), vec![], Some(456), None);
-        dag.insert_branch(branch.clone());
+        dag.insert_branch(branch.clone()).unwrap();
 
-        let result = get_branch_info(&branch, 4, &dag);
+        let result = get_branch_info(&RealGit, &branch, 4, &dag, false, DEFAULT_HASH_ABBREV);
 
         // Test that the function returns a result
         assert!(result.is_ok() || result.is_err());
@@ -0,0 +1,284 @@
+//! An in-memory GitOps for unit tests
+//!
+//! MockGit answers from a canned description of the repository and records every call it
+//! receives, so command logic can be checked without spawning git or gh.
+
+use dagit::dag::{Branch, BranchId, Dag, FailedRebase};
+use dagit::git::{resolve_pr_base, GitError, InteractiveRebase, OriginRebase, PrMetadata, PrState, RebaseOriginError};
+use dagit::git_ops::GitOps;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Default)]
+pub struct MockGit {
+    current_branch: Mutex<String>,
+    branches: HashSet<String>,
+    origin_branches: HashSet<String>,
+    /// (ancestor, descendant) pairs of branch names that is_ancestor answers true for
+    ancestors: HashSet<(String, String)>,
+    /// Branch -> parent that find_closest_parent answers with, if the parent is a candidate
+    closest_parents: HashMap<String, String>,
+    /// Branch -> commit that get_branch_commit answers with, instead of "<branch>-tip"
+    commits: HashMap<String, String>,
+    /// Branches whose rebases fail with a conflict
+    conflicting: HashSet<String>,
+    open_prs: HashSet<usize>,
+    /// Error that get_pr_state fails with for every PR, as when gh is unavailable
+    pr_state_error: Option<String>,
+    next_pr_number: AtomicUsize,
+    calls: Mutex<Vec<String>>,
+}
+
+impl MockGit {
+    /// A repository with the given local branches, currently on the first one
+    pub fn with_branches(branches: &[&str]) -> Self {
+        MockGit {
+            current_branch: Mutex::new(branches.first().map(|b| b.to_string()).unwrap_or_default()),
+            branches: branches.iter().map(|b| b.to_string()).collect(),
+            next_pr_number: AtomicUsize::new(1),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_origin_branches(mut self, branches: &[&str]) -> Self {
+        self.origin_branches.extend(branches.iter().map(|b| b.to_string()));
+        self
+    }
+
+    pub fn with_ancestor(mut self, ancestor: &str, descendant: &str) -> Self {
+        self.ancestors.insert((ancestor.to_string(), descendant.to_string()));
+        self
+    }
+
+    pub fn with_closest_parent(mut self, branch: &str, parent: &str) -> Self {
+        self.closest_parents.insert(branch.to_string(), parent.to_string());
+        self
+    }
+
+    pub fn with_commit(mut self, branch: &str, commit: &str) -> Self {
+        self.commits.insert(branch.to_string(), commit.to_string());
+        self
+    }
+
+    pub fn with_conflict(mut self, branch: &str) -> Self {
+        self.conflicting.insert(branch.to_string());
+        self
+    }
+
+    pub fn with_open_pr(mut self, pr_number: usize) -> Self {
+        self.open_prs.insert(pr_number);
+        self
+    }
+
+    pub fn with_pr_state_error(mut self, error: &str) -> Self {
+        self.pr_state_error = Some(error.to_string());
+        self
+    }
+
+    /// Every call received so far, as "<method> <args...>"
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, method: &str, args: &[&str]) {
+        let call = std::iter::once(method).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn rebase(&self, method: &str, branch: &mut Branch, target_branch: &str) -> Result<(), GitError> {
+        self.record(method, &[&branch.git_name, target_branch]);
+        if self.conflicting.contains(&branch.git_name) {
+            branch.last_failed_rebase = Some(FailedRebase::new(target_branch, format!("{}-tip", branch_name(target_branch))));
+            return Err(GitError::Conflict { branch: branch.git_name.clone(), target: target_branch.to_string(), files: vec![] });
+        }
+        branch.last_failed_rebase = None;
+        Ok(())
+    }
+}
+
+/// Refs are passed around as refs/heads/<name>; the canned data is keyed by plain names
+fn branch_name(reference: &str) -> &str {
+    reference.strip_prefix("refs/heads/").unwrap_or(reference)
+}
+
+impl GitOps for MockGit {
+    fn get_current_git_branch(&self) -> Result<String, String> {
+        Ok(self.current_branch.lock().unwrap().clone())
+    }
+
+    fn is_current_branch(&self, branch_name: &str) -> Result<bool, String> {
+        Ok(*self.current_branch.lock().unwrap() == branch_name)
+    }
+
+    fn is_git_repository(&self) -> Result<bool, String> {
+        Ok(true)
+    }
+
+    fn has_commits(&self) -> bool {
+        !self.branches.is_empty()
+    }
+
+    fn branch_exists(&self, branch_name: &str) -> Result<bool, String> {
+        Ok(self.branches.contains(branch_name))
+    }
+
+    fn origin_branch_exists(&self, branch_name: &str) -> Result<bool, String> {
+        Ok(self.origin_branches.contains(branch_name))
+    }
+
+    fn create_branch_from_origin(&self, branch_name: &str) -> Result<(), String> {
+        self.record("create_branch_from_origin", &[branch_name]);
+        Ok(())
+    }
+
+    fn get_all_branches(&self) -> Result<Vec<String>, String> {
+        let mut branches: Vec<String> = self.branches.iter().cloned().collect();
+        branches.sort();
+        Ok(branches)
+    }
+
+    fn get_branch_commit(&self, branch: &str) -> Result<String, GitError> {
+        let name = branch_name(branch);
+        if let Some(commit) = self.commits.get(name) {
+            Ok(commit.clone())
+        } else if self.branches.contains(name) {
+            Ok(format!("{}-tip", name))
+        } else {
+            Err(GitError::BranchMissing(name.to_string()))
+        }
+    }
+
+    fn count_commits_between_cached(&self, _from: &str, _to: &str) -> Result<u32, String> {
+        Ok(0)
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, GitError> {
+        Ok(self.ancestors.contains(&(branch_name(ancestor).to_string(), branch_name(descendant).to_string())))
+    }
+
+    fn find_closest_parent(&self, target_branch: &str, candidate_branches: &[String], _max_distance: Option<u32>) -> Result<Option<(String, u32)>, String> {
+        Ok(self.closest_parents.get(target_branch)
+            .filter(|parent| candidate_branches.contains(parent))
+            .map(|parent| (parent.clone(), 1)))
+    }
+
+    fn find_closest_children(&self, _target_branch: &str, _candidate_branches: &[String]) -> Result<Vec<String>, String> {
+        Ok(vec![])
+    }
+
+    fn rebase_branch(&self, branch: &mut Branch, target_branch: &str, _keep_empty: bool) -> Result<(), GitError> {
+        self.rebase("rebase_branch", branch, target_branch)
+    }
+
+    fn rebase_branch_updating_refs(&self, branch: &mut Branch, target_branch: &str, _keep_empty: bool) -> Result<(), GitError> {
+        self.rebase("rebase_branch_updating_refs", branch, target_branch)
+    }
+
+    fn checkout_branch(&self, branch_name: &str) -> Result<(), GitError> {
+        self.record("checkout_branch", &[branch_name]);
+        *self.current_branch.lock().unwrap() = branch_name.to_string();
+        Ok(())
+    }
+
+    fn reset_branch(&self, branch_name: &str, commit: &str) -> Result<(), GitError> {
+        self.record("reset_branch", &[branch_name, commit]);
+        Ok(())
+    }
+
+    fn interactive_rebase_branch(&self, branch: &mut Branch, target_branch: &str) -> Result<InteractiveRebase, GitError> {
+        self.rebase("interactive_rebase_branch", branch, target_branch)?;
+        Ok(InteractiveRebase::Completed(format!("{}-tip", branch.git_name)))
+    }
+
+    fn show_diff_since(&self, base: &str, branch: &str) -> Result<(), GitError> {
+        self.record("show_diff_since", &[base, branch]);
+        Ok(())
+    }
+
+    fn show_log_since(&self, base: &str, branch: &str, author: Option<&str>) -> Result<(), GitError> {
+        let author = author.map(|author| format!("--author={}", author));
+        let args: Vec<&str> = [base, branch].into_iter().chain(author.as_deref()).collect();
+        self.record("show_log_since", &args);
+        Ok(())
+    }
+
+    fn fetch_from_origin(&self) -> Result<(), GitError> {
+        self.record("fetch_from_origin", &[]);
+        Ok(())
+    }
+
+    fn rebase_against_origin(&self, branch: &mut Branch, _keep_empty: bool) -> Result<OriginRebase, RebaseOriginError> {
+        if !self.origin_branches.contains(branch.remote_branch_name()) {
+            return Err(RebaseOriginError::OriginDoesntExist);
+        }
+        Ok(OriginRebase::AlreadyUpToDate)
+    }
+
+    fn git_supports_update_refs(&self) -> bool {
+        true
+    }
+
+    fn get_default_branch(&self, _remote: &str) -> Result<String, String> {
+        Ok("main".to_string())
+    }
+
+    fn get_trunk_branch(&self) -> Option<String> {
+        None
+    }
+
+    fn pull_rebase_ff(&self, branch_name: &str, remote: &str, remote_branch: &str) -> Result<(), String> {
+        self.record("pull_rebase_ff", &[branch_name, remote, remote_branch]);
+        Ok(())
+    }
+
+    fn get_remote_url(&self) -> Result<String, String> {
+        Err("MockGit has no remote".to_string())
+    }
+
+    fn get_pr_title_template(&self) -> Option<String> {
+        None
+    }
+
+    fn pr_body(&self, _head: &str, _base: &str) -> String {
+        String::new()
+    }
+
+    /// Opens PRs numbered from 1 for every branch that has a base
+    fn create_pr_for_branch(&self, branch_id: BranchId, dag: &mut Dag, base_override: Option<&str>, _metadata: &PrMetadata, _push: bool) -> Result<Option<usize>, String> {
+        let Some(base) = resolve_pr_base(branch_id, dag, base_override)? else {
+            return Ok(None);
+        };
+        let branch = dag.get_branch_mut(&branch_id).ok_or("Branch not found in DAG")?;
+        if let Some(pr_number) = branch.pr_number {
+            return Ok(Some(pr_number));
+        }
+        self.record("create_pr_for_branch", &[&branch.git_name, &base]);
+        let pr_number = self.next_pr_number.fetch_add(1, Ordering::SeqCst);
+        branch.pr_number = Some(pr_number);
+        Ok(Some(pr_number))
+    }
+
+    fn update_pr_target_for_branch(&self, branch_id: BranchId, dag: &Dag, new_target_branch: &str) -> Result<(), String> {
+        let branch = dag.get_branch(&branch_id).ok_or("Branch not found in DAG")?;
+        self.record("update_pr_target_for_branch", &[&branch.git_name, new_target_branch]);
+        Ok(())
+    }
+
+    fn get_pr_state(&self, pr_number: usize) -> Result<PrState, String> {
+        if let Some(error) = &self.pr_state_error {
+            return Err(error.clone());
+        }
+        Ok(if self.open_prs.contains(&pr_number) { PrState::Open } else { PrState::Merged })
+    }
+
+    fn close_pr(&self, pr_number: usize) -> Result<(), String> {
+        self.record("close_pr", &[&pr_number.to_string()]);
+        Ok(())
+    }
+
+    fn get_hooks_dir(&self) -> Result<PathBuf, String> {
+        Err("MockGit has no hooks directory".to_string())
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashSet;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use crate::dag::Dag;
+use crate::dag::{BranchId, Dag, SCHEMA_VERSION};
 use crate::git;
 
 #[derive(Error, Debug)]
@@ -13,18 +15,37 @@ pub enum SerdeError {
     Json(#[from] serde_json::Error),
     #[error("Git error: {0}")]
     Git(String),
+    #[error("No backup DAG file found at {0}")]
+    NoBackup(PathBuf),
+    #[error("DAG file has schema version {0}, but this dagit only supports up to version {SCHEMA_VERSION}; please upgrade dagit")]
+    UnsupportedVersion(u32),
+    #[error("Invalid DAG file: {0}")]
+    Invalid(String),
 }
 
 /// Get the path to the DAG file, relative to the git repository root
 /// This ensures the DAG file is shared across all worktrees
-fn get_dag_file_path() -> Result<PathBuf, SerdeError> {
+pub fn get_dag_file_path() -> Result<PathBuf, SerdeError> {
     let repo_root = git::get_git_repo_root().map_err(SerdeError::Git)?;
     let dag_path = Path::new(&repo_root).join(".dagit").join("dag.json");
     Ok(dag_path)
 }
 
+/// Get the path to the backup of the previous DAG file (.dagit/dag.json.bak)
+fn get_backup_file_path() -> Result<PathBuf, SerdeError> {
+    let dag_path = get_dag_file_path()?;
+    Ok(dag_path.with_extension("json.bak"))
+}
+
+/// Get the path to the state of the last incomplete update (.dagit/update_state.json)
+fn get_update_state_file_path() -> Result<PathBuf, SerdeError> {
+    let dag_path = get_dag_file_path()?;
+    Ok(dag_path.with_file_name("update_state.json"))
+}
+
 /// Read a DAG from the file at .dagit/dag.json in the git repository root
 /// Returns an empty DAG if the file doesn't exist or can't be read
+/// Files written with an older schema version are migrated and rewritten at the current version
 pub fn read_dag_from_file() -> Result<Dag, SerdeError> {
     let path = get_dag_file_path()?;
 
@@ -40,12 +61,139 @@ pub fn read_dag_from_file() -> Result<Dag, SerdeError> {
         return Ok(Dag::new());
     }
 
-    let dag: Dag = serde_json::from_str(&content)?;
+    let mut value: serde_json::Value = serde_json::from_str(&content)?;
+    let version = value.get("schema_version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or(0) as u32;
+
+    if version > SCHEMA_VERSION {
+        return Err(SerdeError::UnsupportedVersion(version));
+    }
+
+    if version < SCHEMA_VERSION {
+        migrate(&mut value, version);
+        let dag: Dag = serde_json::from_value(value)?;
+        dag.check_branch_ids().map_err(SerdeError::Invalid)?;
+        warn_if_remote_differs(&dag);
+        write_dag_to_file(&dag)?;
+        return Ok(dag);
+    }
+
+    let dag: Dag = serde_json::from_value(value)?;
+    dag.check_branch_ids().map_err(SerdeError::Invalid)?;
+    warn_if_remote_differs(&dag);
 
     Ok(dag)
 }
 
-/// Write a DAG to the file at .dagit/dag.json in the git repository root
+/// Warn when the DAG file was written in a repository with a different origin, which usually
+/// means it was copied from another project and its branch names mean something else here
+fn warn_if_remote_differs(dag: &Dag) {
+    if let Some(warning) = remote_mismatch(dag) {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Describe how the DAG's recorded origin differs from this repository's, if it does
+fn remote_mismatch(dag: &Dag) -> Option<String> {
+    let (Some(stored), Ok(current)) = (&dag.remote_url, git::get_remote_url()) else {
+        return None;
+    };
+
+    (!remotes_match(stored, &current)).then(|| format!(
+        "the DAG file was written for a repository with origin '{}', but this repository's origin is '{}'",
+        stored, current
+    ))
+}
+
+/// Whether two remote URLs point at the same repository, so that e.g. the ssh and https
+/// URLs of one GitHub repository match
+fn remotes_match(a: &str, b: &str) -> bool {
+    match (git::parse_remote_url(a), git::parse_remote_url(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.trim() == b.trim(),
+    }
+}
+
+/// Bring a serialized DAG from the given schema version up to SCHEMA_VERSION, one version at a time
+fn migrate(value: &mut serde_json::Value, from_version: u32) {
+    for version in from_version..SCHEMA_VERSION {
+        match version {
+            // Version 0 files predate schema_version; their missing optional fields are
+            // filled in by #[serde(default)], so only the version needs stamping
+            0 => {}
+            // Version 1 stored only the target of a failed rebase, as a plain string
+            1 => {
+                let branches = value.get_mut("branches").and_then(|branches| branches.as_object_mut());
+                for branch in branches.into_iter().flat_map(|branches| branches.values_mut()) {
+                    if let Some(failed) = branch.get_mut("last_failed_rebase") {
+                        if let Some(target) = failed.as_str() {
+                            *failed = serde_json::json!({ "target": target, "target_commit": "" });
+                        }
+                    }
+                }
+            }
+            _ => unreachable!("No migration from schema version {}", version),
+        }
+    }
+
+    if let Some(object) = value.as_object_mut() {
+        object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    }
+}
+
+/// JSON Schema of the DAG file as written by write_dag_to_file, for validating it outside dagit
+/// Generated from Dag, Branch and FailedRebase (and their doc comments), so it can't drift from them
+pub fn dag_json_schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(Dag);
+    schema.schema.metadata().description = Some(format!("The .dagit/dag.json file, at schema version {}", SCHEMA_VERSION));
+    serde_json::to_value(schema).expect("a JSON Schema serializes to JSON")
+}
+
+/// How the DAG file is laid out, set with `git config dagit.dagFileFormat pretty|compact`
+/// Compact JSON is a single line, which keeps the file small where it is committed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DagFileFormat {
+    #[default]
+    Pretty,
+    Compact,
+}
+
+impl DagFileFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "pretty" => Some(DagFileFormat::Pretty),
+            "compact" => Some(DagFileFormat::Compact),
+            _ => None,
+        }
+    }
+
+    /// The configured format, falling back to pretty (with a warning for unknown values)
+    pub fn configured() -> Self {
+        match git::get_git_config("dagit.dagFileFormat") {
+            Ok(Some(value)) => DagFileFormat::parse(&value).unwrap_or_else(|| {
+                eprintln!("Warning: unknown dagit.dagFileFormat '{}', expected 'pretty' or 'compact'", value);
+                DagFileFormat::Pretty
+            }),
+            _ => DagFileFormat::Pretty,
+        }
+    }
+
+    fn render(self, dag: &Dag) -> Result<String, serde_json::Error> {
+        match self {
+            DagFileFormat::Pretty => serde_json::to_string_pretty(dag),
+            DagFileFormat::Compact => serde_json::to_string(dag),
+        }
+    }
+
+    /// Whether serialized JSON looks like it was written in this format
+    fn matches(self, json: &str) -> bool {
+        json.trim_end().contains('\n') == (self == DagFileFormat::Pretty)
+    }
+}
+
+/// Write a DAG to the file at .dagit/dag.json in the git repository root, in the configured
+/// DagFileFormat
 /// Creates the .dagit directory if it doesn't exist
 /// Overwrites any existing content in the file
 pub fn write_dag_to_file(dag: &Dag) -> Result<(), SerdeError> {
@@ -56,8 +204,31 @@ pub fn write_dag_to_file(dag: &Dag) -> Result<(), SerdeError> {
         fs::create_dir_all(parent)?;
     }
 
-    // Serialize the DAG to JSON with pretty formatting
-    let json = serde_json::to_string_pretty(dag)?;
+    // Record which repository the DAG belongs to the first time it's written there; a URL
+    // that is already stored is kept, so that a DAG from another repository keeps warning
+    let mut dag = dag.clone();
+    if dag.remote_url.is_none() {
+        dag.remote_url = git::get_remote_url().ok();
+    }
+
+    // Leave the file and its backup alone when they'd be unchanged, so that the file's mtime
+    // only moves when the DAG does. The parsed DAGs are compared since the key order of the
+    // serialized branch map isn't stable; a file in the other format is rewritten though
+    let format = DagFileFormat::configured();
+    let unchanged = fs::read_to_string(&path).ok()
+        .filter(|existing| format.matches(existing))
+        .and_then(|existing| serde_json::from_str::<Dag>(&existing).ok())
+        .is_some_and(|existing| existing == dag);
+    if unchanged {
+        return Ok(());
+    }
+
+    // Keep a single previous version around so that it can be restored
+    if fs::metadata(&path).map(|metadata| metadata.len() > 0).unwrap_or(false) {
+        fs::copy(&path, get_backup_file_path()?)?;
+    }
+
+    let json = format.render(&dag)?;
 
     // Write to file, creating it if it doesn't exist or overwriting if it does
     let mut file = fs::File::create(path)?;
@@ -67,18 +238,82 @@ pub fn write_dag_to_file(dag: &Dag) -> Result<(), SerdeError> {
     Ok(())
 }
 
+/// Swap the backup at .dagit/dag.json.bak with the current DAG file
+/// Restoring twice therefore brings back the DAG that was replaced by the first restore
+pub fn restore_dag_from_backup() -> Result<(), SerdeError> {
+    let path = get_dag_file_path()?;
+    let backup_path = get_backup_file_path()?;
+
+    if !backup_path.exists() {
+        return Err(SerdeError::NoBackup(backup_path));
+    }
+
+    if path.exists() {
+        let swap_path = path.with_extension("json.swap");
+        fs::rename(&path, &swap_path)?;
+        fs::rename(&backup_path, &path)?;
+        fs::rename(&swap_path, &backup_path)?;
+    } else {
+        fs::rename(&backup_path, &path)?;
+    }
+
+    Ok(())
+}
+
+/// Progress of an update that didn't finish cleanly, so that `update --resume` can pick it up
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdateState {
+    /// Branches that were updated successfully
+    pub completed: HashSet<BranchId>,
+}
+
+/// Read the state left behind by the last incomplete update
+/// Returns None if there is no such state, i.e. the last update succeeded
+pub fn read_update_state() -> Result<Option<UpdateState>, SerdeError> {
+    let path = get_update_state_file_path()?;
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Write the state of an incomplete update to .dagit/update_state.json
+pub fn write_update_state(state: &UpdateState) -> Result<(), SerdeError> {
+    let path = get_update_state_file_path()?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+/// Remove the update state, if any
+pub fn clear_update_state() -> Result<(), SerdeError> {
+    let path = get_update_state_file_path()?;
+
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::dag::Dag;
+    use crate::dag::{Dag, FailedRebase};
     use std::fs;
     use std::env;
-    use std::path::Path;
     
     /// Helper function to create isolated test functions that work in a temp directory with git initialized
     fn with_temp_dir<F>(test_fn: F)
     where
-        F: FnOnce() -> ()
+        F: FnOnce()
     {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
         let original_dir = env::current_dir().expect("Failed to get current dir");
@@ -180,4 +415,334 @@ mod tests {
             assert!(dag.is_empty());
         });
     }
+    
+    #[test]
+    #[serial_test::serial]
+    fn test_read_migrates_v0_file() {
+        with_temp_dir(|| {
+            // A file from before schema_version existed, with optional fields left out
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            fs::create_dir_all(dag_path.parent().unwrap()).expect("Failed to create .dagit directory");
+            let v0 = r#"{
+                "branches": {
+                    "1": {"uid": 1, "git_name": "main", "children": [2]},
+                    "2": {"uid": 2, "git_name": "feature", "parents": [1], "pr_number": 7}
+                },
+                "next_branch_id": 3
+            }"#;
+            fs::write(&dag_path, v0).expect("Failed to write v0 DAG file");
+
+            let dag = read_dag_from_file().expect("Failed to read v0 DAG");
+            assert_eq!(dag.schema_version, SCHEMA_VERSION);
+            assert_eq!(dag.len(), 2);
+            let main = dag.find_branch_by_name("main").unwrap();
+            assert!(main.parents.is_empty());
+            assert!(main.pr_number.is_none());
+            assert!(main.last_failed_rebase.is_none());
+            assert_eq!(dag.find_branch_by_name("feature").unwrap().pr_number, Some(7));
+
+            // The file is rewritten at the current version
+            let rewritten: serde_json::Value = serde_json::from_str(&fs::read_to_string(&dag_path).unwrap()).unwrap();
+            assert_eq!(rewritten["schema_version"], SCHEMA_VERSION);
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_read_migrates_v1_failed_rebase() {
+        with_temp_dir(|| {
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            fs::create_dir_all(dag_path.parent().unwrap()).expect("Failed to create .dagit directory");
+            let v1 = r#"{
+                "schema_version": 1,
+                "branches": {
+                    "1": {"uid": 1, "git_name": "main", "children": [2]},
+                    "2": {"uid": 2, "git_name": "feature", "parents": [1], "last_failed_rebase": "main"}
+                },
+                "next_branch_id": 3
+            }"#;
+            fs::write(&dag_path, v1).expect("Failed to write v1 DAG file");
+
+            let dag = read_dag_from_file().expect("Failed to read v1 DAG");
+            assert_eq!(dag.find_branch_by_name("feature").unwrap().last_failed_rebase, Some(FailedRebase::new("main", "")));
+            assert!(dag.find_branch_by_name("main").unwrap().last_failed_rebase.is_none());
+        });
+    }
+
+    #[test]
+    fn test_dag_json_schema_matches_serialized_fields() {
+        let mut dag = Dag::new();
+        let main = dag.create_branch("main".to_string());
+        let feature = dag.create_branch("feature".to_string());
+        dag.add_parent_child_relationship_by_id(feature, main).unwrap();
+        dag.remote_url = Some("git@github.com:owner/repo.git".to_string());
+        dag.submit_order = vec![main, feature];
+        dag.trunk = Some(main);
+        let branch = dag.get_branch_mut(&feature).unwrap();
+        branch.last_failed_rebase = Some(FailedRebase::new("main", "abc1234"));
+        branch.pr_number = Some(3);
+        branch.note = Some("wip".to_string());
+        branch.remote_name = Some("alice/feature".to_string());
+
+        let schema = dag_json_schema();
+        let definitions = &schema["definitions"];
+        let serialized = serde_json::to_value(&dag).unwrap();
+        let branch = &serialized["branches"][feature.0.to_string()];
+        for (value, object_schema) in [
+            (&serialized, &schema),
+            (branch, &definitions["Branch"]),
+            (&branch["last_failed_rebase"], &definitions["FailedRebase"]),
+        ] {
+            let properties = object_schema["properties"].as_object().unwrap();
+            let values = value.as_object().unwrap();
+            assert_eq!(properties.keys().collect::<Vec<_>>(), values.keys().collect::<Vec<_>>());
+
+            // Every value has one of the types its property allows
+            for (key, value) in values {
+                let Some(types) = properties[key].get("type") else { continue };
+                let json_type = match value {
+                    serde_json::Value::Null => "null",
+                    serde_json::Value::Bool(_) => "boolean",
+                    serde_json::Value::Number(_) => "integer",
+                    serde_json::Value::String(_) => "string",
+                    serde_json::Value::Array(_) => "array",
+                    serde_json::Value::Object(_) => "object",
+                };
+                let allowed = types.as_array().cloned().unwrap_or_else(|| vec![types.clone()]);
+                assert!(allowed.contains(&json_type.into()), "{} is {}, schema allows {}", key, json_type, types);
+            }
+        }
+
+        // Only the fields without a serde default are required
+        assert_eq!(schema["required"], serde_json::json!(["branches"]));
+        assert_eq!(definitions["Branch"]["required"], serde_json::json!(["git_name", "uid"]));
+        assert_eq!(definitions["FailedRebase"]["required"], serde_json::json!(["target"]));
+        let minimal = serde_json::json!({
+            "branches": { "1": { "uid": 1, "git_name": "main", "last_failed_rebase": { "target": "origin/main" } } },
+        });
+        assert!(serde_json::from_value::<Dag>(minimal).is_ok());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_read_rejects_newer_schema_version() {
+        with_temp_dir(|| {
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            fs::create_dir_all(dag_path.parent().unwrap()).expect("Failed to create .dagit directory");
+            fs::write(&dag_path, r#"{"schema_version": 999, "branches": {}}"#).expect("Failed to write DAG file");
+
+            assert!(matches!(read_dag_from_file(), Err(SerdeError::UnsupportedVersion(999))));
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_read_rejects_branch_ids_too_large_to_allocate_from() {
+        with_temp_dir(|| {
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            fs::create_dir_all(dag_path.parent().unwrap()).expect("Failed to create .dagit directory");
+            let huge = usize::MAX;
+
+            fs::write(&dag_path, format!(r#"{{"schema_version": {}, "branches": {{}}, "next_branch_id": {}}}"#, SCHEMA_VERSION, huge))
+                .expect("Failed to write DAG file");
+            assert!(matches!(read_dag_from_file(), Err(SerdeError::Invalid(message)) if message.contains("next_branch_id")));
+
+            fs::write(&dag_path, format!(
+                r#"{{"schema_version": {}, "branches": {{"{huge}": {{"uid": {huge}, "git_name": "main"}}}}, "next_branch_id": 1}}"#,
+                SCHEMA_VERSION,
+            )).expect("Failed to write DAG file");
+            assert!(matches!(read_dag_from_file(), Err(SerdeError::Invalid(message)) if message.contains("Branch ID")));
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_write_keeps_backup_and_restore_swaps() {
+        with_temp_dir(|| {
+            let mut first_dag = Dag::new();
+            first_dag.create_branch("main".to_string());
+            write_dag_to_file(&first_dag).expect("Failed to write DAG");
+
+            // Nothing to back up on the first write
+            let backup_path = get_backup_file_path().expect("Failed to get backup path");
+            assert!(!backup_path.exists(), "No backup should exist after the first write");
+
+            let mut second_dag = first_dag.clone();
+            second_dag.create_branch("feature".to_string());
+            write_dag_to_file(&second_dag).expect("Failed to write DAG");
+            assert!(backup_path.exists(), "Backup should exist after overwriting");
+
+            restore_dag_from_backup().expect("Failed to restore DAG");
+            assert_eq!(read_dag_from_file().expect("Failed to read DAG"), first_dag);
+
+            // Restoring again undoes the restore
+            restore_dag_from_backup().expect("Failed to restore DAG");
+            assert_eq!(read_dag_from_file().expect("Failed to read DAG"), second_dag);
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_write_does_not_back_up_empty_file() {
+        with_temp_dir(|| {
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            if let Some(parent) = dag_path.parent() {
+                fs::create_dir_all(parent).expect("Failed to create .dagit directory");
+            }
+            fs::write(&dag_path, "").expect("Failed to create empty file");
+
+            write_dag_to_file(&Dag::new()).expect("Failed to write DAG");
+
+            let backup_path = get_backup_file_path().expect("Failed to get backup path");
+            assert!(!backup_path.exists(), "An empty DAG file should not be backed up");
+            assert!(matches!(restore_dag_from_backup(), Err(SerdeError::NoBackup(_))));
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_update_state_round_trip_and_clear() {
+        with_temp_dir(|| {
+            assert_eq!(read_update_state().unwrap(), None);
+
+            let state = UpdateState {
+                completed: [BranchId(1), BranchId(3)].into_iter().collect(),
+            };
+            write_update_state(&state).expect("Failed to write update state");
+            assert_eq!(read_update_state().unwrap(), Some(state));
+
+            clear_update_state().expect("Failed to clear update state");
+            assert_eq!(read_update_state().unwrap(), None);
+
+            // Clearing without a state file is a no-op
+            clear_update_state().expect("Clearing twice should succeed");
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_write_records_remote_url() {
+        with_temp_dir(|| {
+            std::process::Command::new("git")
+                .args(["remote", "add", "origin", "git@github.com:owner/repo.git"])
+                .output()
+                .expect("Failed to add origin");
+
+            write_dag_to_file(&Dag::new()).expect("Failed to write DAG");
+
+            let read_dag = read_dag_from_file().expect("Failed to read DAG");
+            assert_eq!(read_dag.remote_url.as_deref(), Some("git@github.com:owner/repo.git"));
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_migration_keeps_remote_url_of_another_repository() {
+        with_temp_dir(|| {
+            std::process::Command::new("git")
+                .args(["remote", "add", "origin", "git@github.com:owner/repo.git"])
+                .output()
+                .expect("Failed to add origin");
+            let dag_path = get_dag_file_path().unwrap();
+            fs::create_dir_all(dag_path.parent().unwrap()).unwrap();
+            let v1 = r#"{
+                "schema_version": 1,
+                "branches": {"1": {"uid": 1, "git_name": "main"}},
+                "next_branch_id": 2,
+                "remote_url": "git@github.com:owner/other.git"
+            }"#;
+            fs::write(&dag_path, v1).expect("Failed to write v1 DAG file");
+
+            // The first read migrates and rewrites the file, the second must still warn
+            let migrated = read_dag_from_file().expect("Failed to read v1 DAG");
+            assert!(remote_mismatch(&migrated).is_some());
+            let reread = read_dag_from_file().expect("Failed to read migrated DAG");
+            assert_eq!(reread.remote_url.as_deref(), Some("git@github.com:owner/other.git"));
+            assert!(remote_mismatch(&reread).is_some());
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_write_skips_unchanged_dag() {
+        with_temp_dir(|| {
+            let mut dag = Dag::new();
+            dag.create_branch("main".to_string());
+            dag.create_branch("feature".to_string());
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            let modified = || fs::metadata(&dag_path).and_then(|metadata| metadata.modified()).expect("Failed to read mtime");
+            let before = modified();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+
+            // Like tracking an already tracked branch: the DAG is saved without changes
+            write_dag_to_file(&read_dag_from_file().expect("Failed to read DAG")).expect("Failed to write DAG");
+            assert_eq!(modified(), before);
+            assert!(!get_backup_file_path().expect("Failed to get backup path").exists());
+
+            dag.create_branch("fix".to_string());
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+            assert_ne!(modified(), before);
+        });
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_write_in_configured_format() {
+        with_temp_dir(|| {
+            let mut dag = Dag::new();
+            dag.create_branch("main".to_string());
+            let dag_path = get_dag_file_path().expect("Failed to get DAG file path");
+            let set_format = |format: &str| {
+                std::process::Command::new("git")
+                    .args(["config", "dagit.dagFileFormat", format])
+                    .output()
+                    .expect("Failed to set dagit.dagFileFormat");
+            };
+
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+            assert!(fs::read_to_string(&dag_path).unwrap().lines().count() > 1, "pretty is the default");
+
+            // Switching formats rewrites the file even though the DAG didn't change
+            set_format("compact");
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+            assert_eq!(fs::read_to_string(&dag_path).unwrap().lines().count(), 1);
+            assert_eq!(read_dag_from_file().expect("Failed to read compact DAG"), dag);
+
+            set_format("pretty");
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+            assert!(fs::read_to_string(&dag_path).unwrap().lines().count() > 1);
+        });
+
+        assert_eq!(DagFileFormat::parse("Compact"), Some(DagFileFormat::Compact));
+        assert_eq!(DagFileFormat::parse("yaml"), None);
+    }
+
+    #[test]
+    fn test_remotes_match() {
+        assert!(remotes_match("git@github.com:owner/repo.git", "https://github.com/owner/repo"));
+        assert!(remotes_match("/tmp/origin", "/tmp/origin\n"));
+        assert!(!remotes_match("git@github.com:owner/repo.git", "git@github.com:owner/other.git"));
+        assert!(!remotes_match("/tmp/origin", "/tmp/other"));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_read_from_nested_subdirectory() {
+        with_temp_dir(|| {
+            let mut dag = Dag::new();
+            dag.create_branch("main".to_string());
+            write_dag_to_file(&dag).expect("Failed to write DAG");
+
+            fs::create_dir_all("src/nested/deeper").expect("Failed to create nested dirs");
+            let repo_root = env::current_dir().expect("Failed to get current dir");
+            env::set_current_dir("src/nested/deeper").expect("Failed to change to nested dir");
+            let read_dag = read_dag_from_file();
+            env::set_current_dir(repo_root).expect("Failed to return to repo root");
+
+            assert_eq!(read_dag.expect("Failed to read DAG from a subdirectory"), dag);
+            assert!(!Path::new("src/nested/deeper/.dagit").exists());
+        });
+    }
 }